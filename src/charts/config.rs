@@ -0,0 +1,22 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ChartsConfig {
+    /// TrueType font used for all chart text; it must cover Cyrillic
+    #[serde(default = "default_font_path")]
+    pub font_path: String,
+}
+
+fn default_font_path() -> String {
+    // Shipped by the `fonts-dejavu-core` package on Debian
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf".to_string()
+}
+
+impl Default for ChartsConfig {
+    fn default() -> Self {
+        Self {
+            font_path: default_font_path(),
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
+
+use chrono::{Datelike, Days, FixedOffset, NaiveDate, Timelike};
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use plotters::style::FontStyle;
+
+use crate::database_actions::day::Day;
+use crate::localization::{tr, Lang};
+
+pub mod config;
+
+/// Font family name the loaded font is registered under
+const FONT: &str = "sans-serif";
+
+const NO_DATA_COLOR: RGBColor = RGBColor(235, 237, 240);
+const TIE_COLOR: RGBColor = RGBColor(250, 227, 150);
+const ON_TIME_COLOR: RGBColor = RGBColor(123, 201, 111);
+const LATE_LIGHT_COLOR: RGBColor = RGBColor(250, 160, 140);
+const LATE_DARK_COLOR: RGBColor = RGBColor(190, 30, 30);
+
+#[derive(Debug)]
+pub enum ChartError {
+    /// There is nothing to draw
+    NoData,
+    /// The chart font could not be loaded
+    Font(String),
+    /// Drawing or PNG encoding failed
+    Render(String),
+}
+
+impl fmt::Display for ChartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChartError::NoData => write!(f, "no data to draw"),
+            ChartError::Font(e) => write!(f, "failed to load chart font: {}", e),
+            ChartError::Render(e) => write!(f, "failed to render chart: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ChartError {}
+
+impl<E: std::error::Error + Send + Sync> From<DrawingAreaErrorKind<E>> for ChartError {
+    fn from(e: DrawingAreaErrorKind<E>) -> Self {
+        ChartError::Render(e.to_string())
+    }
+}
+
+/// Loads the font used for chart text. Must be called once before rendering.
+pub fn load_font<P: AsRef<Path>>(path: P) -> Result<(), ChartError> {
+    let bytes = std::fs::read(path.as_ref())
+        .map_err(|e| ChartError::Font(format!("{}: {}", path.as_ref().display(), e)))?;
+    // plotters keeps registered fonts for the lifetime of the process
+    let bytes: &'static [u8] = Box::leak(bytes.into_boxed_slice());
+    plotters::style::register_font(FONT, FontStyle::Normal, bytes)
+        .map_err(|_| ChartError::Font(format!("{}: not a valid font", path.as_ref().display())))
+}
+
+/// Renders an RGB drawing into a PNG file in memory
+fn render_png<F>(width: u32, height: u32, draw: F) -> Result<Vec<u8>, ChartError>
+where
+    F: FnOnce(&DrawingArea<BitMapBackend, Shift>) -> Result<(), ChartError>,
+{
+    let mut pixels = vec![0u8; (width * height * 3) as usize];
+    {
+        let root = BitMapBackend::with_buffer(&mut pixels, (width, height)).into_drawing_area();
+        root.fill(&WHITE)?;
+        draw(&root)?;
+        root.present()?;
+    }
+
+    let mut png_bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png_bytes, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder
+        .write_header()
+        .map_err(|e| ChartError::Render(e.to_string()))?;
+    writer
+        .write_image_data(&pixels)
+        .map_err(|e| ChartError::Render(e.to_string()))?;
+    writer.finish().map_err(|e| ChartError::Render(e.to_string()))?;
+    Ok(png_bytes)
+}
+
+fn mix(from: RGBColor, to: RGBColor, ratio: f64) -> RGBColor {
+    let channel = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * ratio.clamp(0.0, 1.0)).round() as u8;
+    RGBColor(channel(from.0, to.0), channel(from.1, to.1), channel(from.2, to.2))
+}
+
+fn day_color(day: Option<&Day>) -> RGBColor {
+    let Some(day) = day else {
+        return NO_DATA_COLOR;
+    };
+    let (yes, no) = (day.votes_yes.len(), day.votes_no.len());
+    if yes + no == 0 {
+        NO_DATA_COLOR
+    } else if yes > no {
+        // The more one-sided the vote, the darker the cell
+        let share = yes as f64 / (yes + no) as f64;
+        mix(LATE_LIGHT_COLOR, LATE_DARK_COLOR, (share - 0.5) * 2.0)
+    } else if no > yes {
+        ON_TIME_COLOR
+    } else {
+        TIE_COLOR
+    }
+}
+
+/// Renders a GitHub-style calendar of the given days: one column per week, one row per weekday
+pub fn render_calendar_heatmap(days: &[Day], lang: Lang) -> Result<Vec<u8>, ChartError> {
+    const CELL: i32 = 14;
+    const STEP: i32 = 17;
+    const LEFT: i32 = 40;
+    const TOP: i32 = 50;
+
+    let by_date: HashMap<NaiveDate, &Day> = days.iter().map(|day| (day.naive_date(), day)).collect();
+    let first = *by_date.keys().min().ok_or(ChartError::NoData)?;
+    let last = *by_date.keys().max().ok_or(ChartError::NoData)?;
+    let start = first - Days::new(first.weekday().num_days_from_monday() as u64);
+    let weeks = ((last - start).num_days() / 7 + 1) as i32;
+
+    let width = (LEFT + weeks * STEP + 20).max(360) as u32;
+    let height = (TOP + 7 * STEP + 45) as u32;
+
+    render_png(width, height, |root| {
+        let text = |size| (FONT, size).into_font().color(&BLACK);
+        root.draw(&Text::new(tr(lang, "chart_calendar_title"), (LEFT, 8), text(18)))?;
+
+        for (row, key) in [(0, "weekday_short_1"), (2, "weekday_short_3"), (4, "weekday_short_5")] {
+            root.draw(&Text::new(tr(lang, key), (4, TOP + row * STEP + 1), text(11)))?;
+        }
+
+        let mut previous_month = None;
+        for week in 0..weeks {
+            let week_start = start + Days::new(week as u64 * 7);
+            if previous_month != Some(week_start.month()) {
+                previous_month = Some(week_start.month());
+                root.draw(&Text::new(
+                    week_start.format("%m.%y").to_string(),
+                    (LEFT + week * STEP, TOP - 16),
+                    text(11),
+                ))?;
+            }
+
+            for weekday in 0..7 {
+                let date = week_start + Days::new(weekday as u64);
+                if date > last {
+                    break;
+                }
+                let (x, y) = (LEFT + week * STEP, TOP + weekday * STEP);
+                root.draw(&Rectangle::new(
+                    [(x, y), (x + CELL, y + CELL)],
+                    day_color(by_date.get(&date).copied()).filled(),
+                ))?;
+            }
+        }
+
+        let legend_y = TOP + 7 * STEP + 15;
+        let mut legend_x = LEFT;
+        for (color, key) in [
+            (NO_DATA_COLOR, "chart_legend_no_data"),
+            (ON_TIME_COLOR, "chart_legend_on_time"),
+            (TIE_COLOR, "chart_legend_tie"),
+            (LATE_DARK_COLOR, "chart_legend_late"),
+        ] {
+            root.draw(&Rectangle::new(
+                [(legend_x, legend_y), (legend_x + CELL, legend_y + CELL)],
+                color.filled(),
+            ))?;
+            let label = tr(lang, key);
+            root.draw(&Text::new(label.clone(), (legend_x + CELL + 4, legend_y + 1), text(11)))?;
+            legend_x += CELL + 12 + label.chars().count() as i32 * 7;
+        }
+        Ok(())
+    })
+}
+
+/// Renders a bar chart of late days per calendar month
+pub fn render_monthly_bars(days: &[Day], lang: Lang) -> Result<Vec<u8>, ChartError> {
+    let mut per_month: BTreeMap<(i32, u32), u32> = BTreeMap::new();
+    for day in days {
+        let date = day.naive_date();
+        *per_month.entry((date.year(), date.month())).or_default() += day.is_late() as u32;
+    }
+
+    // Fill in months without any documents so gaps are visible
+    let (&(first_year, first_month), _) = per_month.iter().next().ok_or(ChartError::NoData)?;
+    let (&last, _) = per_month.iter().next_back().ok_or(ChartError::NoData)?;
+    let mut months = Vec::new();
+    let (mut year, mut month) = (first_year, first_month);
+    while (year, month) <= last {
+        months.push(((year, month), per_month.get(&(year, month)).copied().unwrap_or(0)));
+        (year, month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+    }
+
+    let max = months.iter().map(|(_, count)| *count).max().unwrap_or(0).max(1);
+    let width = (120 + months.len() as u32 * 50).max(480);
+
+    render_png(width, 400, |root| {
+        let mut chart = ChartBuilder::on(root)
+            .caption(tr(lang, "chart_months_title"), (FONT, 20))
+            .margin(15)
+            .x_label_area_size(30)
+            .y_label_area_size(40)
+            // A segmented range has one segment per value including its end, so stop at the last index
+            .build_cartesian_2d((0..months.len() as u32 - 1).into_segmented(), 0..max + 1)?;
+
+        chart
+            .configure_mesh()
+            .disable_x_mesh()
+            .x_labels(months.len())
+            .x_label_style((FONT, 12))
+            .y_label_style((FONT, 12))
+            .y_desc(tr(lang, "chart_months_axis"))
+            .x_label_formatter(&|value| match value {
+                SegmentValue::CenterOf(index) | SegmentValue::Exact(index) => months
+                    .get(*index as usize)
+                    .map(|((year, month), _)| format!("{:02}.{:02}", month, year % 100))
+                    .unwrap_or_default(),
+                SegmentValue::Last => String::new(),
+            })
+            .draw()?;
+
+        chart.draw_series(
+            Histogram::vertical(&chart)
+                .style(LATE_DARK_COLOR.filled())
+                .margin(8)
+                .data(months.iter().enumerate().map(|(index, (_, count))| (index as u32, *count))),
+        )?;
+        Ok(())
+    })
+}
+
+/// Renders how the "late" and "on time" tallies of a day changed over time.
+///
+/// Times are shown in the zone given by `offset`.
+pub fn render_day_votes(day: &Day, lang: Lang, offset: FixedOffset) -> Result<Vec<u8>, ChartError> {
+    if day.vote_log.is_empty() {
+        return Err(ChartError::NoData);
+    }
+
+    let hour_of = |millis: i64| {
+        let time = chrono::DateTime::from_timestamp_millis(millis)
+            .unwrap_or_default()
+            .with_timezone(&offset);
+        time.hour() as f64 + time.minute() as f64 / 60.0 + time.second() as f64 / 3600.0
+    };
+
+    // Replay the log; each user's latest event decides which side they are on
+    let mut votes: HashMap<i64, bool> = HashMap::new();
+    let mut late_points = vec![];
+    let mut on_time_points = vec![];
+    let tally = |votes: &HashMap<i64, bool>| {
+        let late = votes.values().filter(|&&vote| vote).count() as u32;
+        (late, votes.len() as u32 - late)
+    };
+    for event in &day.vote_log {
+        let hour = hour_of(event.at.timestamp_millis());
+        let (late_before, on_time_before) = tally(&votes);
+        match event.vote {
+            Some(vote) => votes.insert(event.user_id, vote),
+            None => votes.remove(&event.user_id),
+        };
+        let (late, on_time) = tally(&votes);
+        late_points.extend([(hour, late_before), (hour, late)]);
+        on_time_points.extend([(hour, on_time_before), (hour, on_time)]);
+    }
+
+    let first_hour = late_points.first().map(|(hour, _)| hour.floor()).unwrap_or(0.0);
+    let last_hour = late_points.last().map(|(hour, _)| hour.ceil()).unwrap_or(24.0);
+    let last_hour = last_hour.max(first_hour + 1.0);
+    let max = late_points
+        .iter()
+        .chain(&on_time_points)
+        .map(|(_, count)| *count)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+
+    // Extend both lines to the end of the axis so the final tally is visible
+    if let (Some(&(_, late)), Some(&(_, on_time))) = (late_points.last(), on_time_points.last()) {
+        late_points.push((last_hour, late));
+        on_time_points.push((last_hour, on_time));
+    }
+
+    render_png(640, 400, |root| {
+        let mut chart = ChartBuilder::on(root)
+            .caption(
+                format!("{} {}", tr(lang, "chart_day_title"), day.naive_date().format("%d.%m.%Y")),
+                (FONT, 20),
+            )
+            .margin(15)
+            .x_label_area_size(30)
+            .y_label_area_size(40)
+            .build_cartesian_2d(first_hour..last_hour, 0..max + 1)?;
+
+        chart
+            .configure_mesh()
+            .x_label_style((FONT, 12))
+            .y_label_style((FONT, 12))
+            .x_label_formatter(&|hour| {
+                let minutes = (hour * 60.0).round() as i64;
+                format!("{:02}:{:02}", minutes / 60, minutes % 60)
+            })
+            .y_desc(tr(lang, "chart_day_axis"))
+            .draw()?;
+
+        chart
+            .draw_series(LineSeries::new(late_points, LATE_DARK_COLOR.stroke_width(2)))?
+            .label(tr(lang, "chart_legend_late"))
+            .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], LATE_DARK_COLOR.stroke_width(2)));
+        chart
+            .draw_series(LineSeries::new(on_time_points, ON_TIME_COLOR.stroke_width(2)))?
+            .label(tr(lang, "chart_legend_on_time"))
+            .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], ON_TIME_COLOR.stroke_width(2)));
+
+        chart
+            .configure_series_labels()
+            .label_font((FONT, 12))
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .position(SeriesLabelPosition::UpperLeft)
+            .draw()?;
+        Ok(())
+    })
+}
@@ -0,0 +1,116 @@
+//! `latebot admin`: runs console commands in a running bot through its admin socket or port.
+
+use std::error::Error;
+use std::io::{BufRead, IsTerminal};
+use std::path::PathBuf;
+
+use rustyline::{error::ReadlineError, DefaultEditor};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::config::secret::Secret;
+use crate::console::config::AdminConfig;
+
+/// Where the bot serves the console commands
+pub enum Endpoint {
+    #[cfg(unix)]
+    Socket(PathBuf),
+    /// The port and the token sent before each command
+    Tcp(u16, Option<Secret>),
+}
+
+impl Endpoint {
+    /// The socket or port given on the command line, else the socket or port from the config.
+    /// The port is always sent the config's token
+    pub fn resolve(socket: Option<PathBuf>, port: Option<u16>, config: &AdminConfig) -> Result<Self, String> {
+        if let Some(port) = port {
+            return Ok(Endpoint::Tcp(port, config.tcp_token.clone()));
+        }
+        #[cfg(unix)]
+        if let Some(socket) = socket {
+            return Ok(Endpoint::Socket(socket));
+        }
+        #[cfg(unix)]
+        if !config.socket_path.is_empty() {
+            return Ok(Endpoint::Socket(PathBuf::from(&config.socket_path)));
+        }
+        #[cfg(not(unix))]
+        let _ = socket;
+        match config.tcp_port {
+            0 => Err("the admin socket and port are both disabled in the config".to_string()),
+            port => Ok(Endpoint::Tcp(port, config.tcp_token.clone())),
+        }
+    }
+
+    /// Sends one command line and returns the bot's answer
+    async fn send(&self, command: &str) -> Result<String, Box<dyn Error>> {
+        match self {
+            #[cfg(unix)]
+            Endpoint::Socket(path) => {
+                let stream = tokio::net::UnixStream::connect(path)
+                    .await
+                    .map_err(|e| format!("can't connect to {}: {}; is the bot running?", path.display(), e))?;
+                exchange(stream, command).await
+            }
+            Endpoint::Tcp(port, token) => {
+                let token = token.as_ref().ok_or("admin.tcp_token must be set to use the admin port")?;
+                let stream = tokio::net::TcpStream::connect(("127.0.0.1", *port))
+                    .await
+                    .map_err(|e| format!("can't connect to 127.0.0.1:{}: {}; is the bot running?", port, e))?;
+                exchange(stream, &format!("{}\n{}", token.expose(), command)).await
+            }
+        }
+    }
+}
+
+async fn exchange<S>(mut stream: S, command: &str) -> Result<String, Box<dyn Error>>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    stream.write_all(format!("{}\n", command).as_bytes()).await?;
+    let mut answer = String::new();
+    stream.read_to_string(&mut answer).await?;
+    Ok(answer)
+}
+
+/// Runs `command` if given; otherwise reads commands from stdin, with line editing when it
+/// is a terminal, until `exit` or end of input
+pub async fn run(endpoint: Endpoint, command: Vec<String>) -> Result<(), Box<dyn Error>> {
+    if !command.is_empty() {
+        print!("{}", endpoint.send(&command.join(" ")).await?);
+        return Ok(());
+    }
+
+    if !std::io::stdin().is_terminal() {
+        for line in std::io::stdin().lock().lines() {
+            match line?.trim() {
+                "exit" => break,
+                "" => {}
+                command => print!("{}", endpoint.send(command).await?),
+            }
+        }
+        return Ok(());
+    }
+
+    let mut editor = DefaultEditor::new()?;
+    println!("Connected to the bot's admin commands. Type 'help' for available commands, 'exit' to leave.");
+    loop {
+        let input = match editor.readline("latebot admin> ") {
+            Ok(input) => input,
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        match input.trim() {
+            "exit" => break,
+            "" => {}
+            command => {
+                let _ = editor.add_history_entry(command);
+                // A failed command doesn't end the session; the bot may be restarting
+                match endpoint.send(command).await {
+                    Ok(answer) => print!("{}", answer),
+                    Err(e) => eprintln!("{}", e),
+                }
+            }
+        }
+    }
+    Ok(())
+}
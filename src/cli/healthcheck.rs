@@ -0,0 +1,38 @@
+//! `latebot healthcheck`: asks the running bot's health endpoint whether it is ready, for
+//! Docker's `HEALTHCHECK`.
+
+use std::error::Error;
+
+use crate::health::{config::HealthConfig, server::probe};
+
+/// Checks `/readyz`, or `/healthz` with `live`; fails unless the bot answers 200
+pub async fn run(address: Option<String>, live: bool, config: &HealthConfig) -> Result<(), Box<dyn Error>> {
+    let address = match address {
+        Some(address) => address,
+        None if config.bind_address.is_empty() => {
+            return Err("the health endpoints are disabled; set health.bind_address".into())
+        }
+        None => local_address(&config.bind_address),
+    };
+    let path = if live { "/healthz" } else { "/readyz" };
+
+    let (ok, body) = probe(&address, path)
+        .await
+        .map_err(|e| format!("no answer from http://{}{}: {}", address, path, e))?;
+    print!("{}", body);
+    if ok {
+        Ok(())
+    } else {
+        Err(format!("http://{}{} reports the bot is not healthy", address, path).into())
+    }
+}
+
+/// The address to connect to for a server bound to `bind_address`; a wildcard address
+/// is reached through loopback
+fn local_address(bind_address: &str) -> String {
+    match bind_address.rsplit_once(':') {
+        Some(("0.0.0.0", port)) => format!("127.0.0.1:{}", port),
+        Some(("[::]", port)) => format!("[::1]:{}", port),
+        _ => bind_address.to_string(),
+    }
+}
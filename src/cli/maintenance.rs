@@ -0,0 +1,104 @@
+//! Maintenance subcommands: they talk to the database and print to stdout, without
+//! starting the Telegram dispatcher.
+
+use std::error::Error;
+use std::io::Write;
+
+use chrono::Utc;
+
+use crate::config::Config;
+use crate::database_actions::{
+    day::Day, migrations::SCHEMA_VERSION, DatabaseService, DatabaseServiceInner, StoreError,
+};
+use crate::handlers::common::{summary_message, today_stats_message};
+use crate::history::{
+    export::{load_records, serialize_records},
+    import::{apply_import, parse_records, plan_import, render_invalid, render_plan},
+    HistoryFormat,
+};
+use crate::localization::Lang;
+
+use super::Command;
+
+/// Runs a maintenance `command`; [`Command::Run`], [`Command::CheckConfig`],
+/// [`Command::Admin`] and [`Command::Healthcheck`] are handled by `main`
+pub async fn run(command: Command, config: &Config) -> Result<(), Box<dyn Error>> {
+    let database_service = connect(config).await?;
+
+    match command {
+        Command::Export {
+            format,
+            output,
+            from,
+            to,
+        } => {
+            let records = load_records(&database_service, from, to).await?;
+            let bytes = serialize_records(&records, format.into()).map_err(|e| e.to_string())?;
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, bytes)?;
+                    eprintln!("Exported {} days to {}", records.len(), path.display());
+                }
+                None => std::io::stdout().write_all(&bytes)?,
+            }
+        }
+        Command::Import { path, commit } => {
+            let contents = std::fs::read(&path)?;
+            let format = HistoryFormat::detect(path.to_str(), &contents);
+            let records = parse_records(&contents, format, Utc::now().date_naive())
+                .map_err(|errors| render_invalid(Lang::En, &errors))?;
+
+            let plan = plan_import(&database_service, &records).await?;
+            if commit {
+                apply_import(&database_service, &plan).await?;
+            }
+            println!("{}", render_plan(Lang::En, &plan, commit));
+            if !commit && !plan.changes.is_empty() {
+                println!("\nRun again with --commit to save these changes.");
+            }
+        }
+        Command::Migrate => {
+            let applied = database_service.run_migrations().await?;
+            database_service.ensure_indexes().await;
+            let version = database_service.schema_version().await?;
+            println!(
+                "Applied {} migration(s); schema version {} (this build supports {})",
+                applied, version, SCHEMA_VERSION
+            );
+        }
+        Command::Stats { date } => {
+            let lang = config.bot.default_lang();
+            let date = date.unwrap_or_else(|| Utc::now().date_naive());
+
+            let total_late_days = database_service.get_total_late_days().await?;
+            let total_days = database_service.get_total_days().await?;
+            println!(
+                "{}",
+                summary_message(lang, &config.bot.target_name, total_late_days as u64, total_days)
+            );
+
+            let day = match database_service.get_day_stats(Day::start_of(date)).await {
+                Ok(day) => day,
+                Err(StoreError::NotFound(_)) => Day::new(Day::start_of(date)),
+                Err(e) => return Err(e.into()),
+            };
+            println!("\n{}:\n{}", date, today_stats_message(lang, &day, None));
+        }
+        Command::Run
+        | Command::CheckConfig
+        | Command::Config { .. }
+        | Command::Admin { .. }
+        | Command::Healthcheck { .. } => unreachable!("handled by main"),
+    }
+    Ok(())
+}
+
+/// Connects to the database, failing right away if it is unreachable.
+///
+/// Unlike the bot this neither waits for the database nor replays the vote journal, which
+/// belongs to the bot process.
+async fn connect(config: &Config) -> Result<DatabaseService, StoreError> {
+    let database_service = DatabaseServiceInner::new(&config.database).await?;
+    database_service.ping().await?;
+    Ok(database_service)
+}
@@ -0,0 +1,220 @@
+//! Command-line arguments of the `latebot` binary.
+//!
+//! Without a subcommand the bot runs as before. Most other subcommands are maintenance
+//! tasks that only need the database, so they work without a Telegram token and alongside
+//! a running bot; `admin` and `healthcheck` talk to a running bot instead.
+
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand, ValueEnum};
+
+use crate::config::{format::ConfigFormat, template::render_default};
+use crate::history::HistoryFormat;
+
+pub mod admin;
+pub mod healthcheck;
+pub mod maintenance;
+
+#[derive(Parser, Debug)]
+#[command(name = "latebot", version, about = "Telegram bot tracking whether someone is late")]
+pub struct Cli {
+    /// Config file in JSON, TOML or YAML; settings missing from it come from defaults and
+    /// LATEBOT_* variables. Defaults to the first of config.json, config.toml, config.yaml
+    /// and config.yml that exists
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+    /// Use built-in values for settings that aren't configured, e.g. the database address
+    #[arg(long, global = true)]
+    pub allow_defaults: bool,
+    /// Directory of the log file written by `run`; other commands log to stderr only
+    #[arg(long, global = true, default_value = ".")]
+    pub log_dir: PathBuf,
+    /// Least severe messages that are logged
+    #[arg(long, global = true, value_enum, default_value_t = LogLevel::Info)]
+    pub log_level: LogLevel,
+    /// Same as the `check-config` subcommand
+    #[arg(long, hide = true)]
+    pub check_config: bool,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Run the bot (the default)
+    Run,
+    /// Validate the configuration and show where each setting comes from
+    CheckConfig,
+    /// Work with config files
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Write the voting history as CSV or JSON
+    Export {
+        #[arg(long, value_enum, default_value_t = FormatArg::Csv)]
+        format: FormatArg,
+        /// File to write; standard output when omitted
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// First day to include, YYYY-MM-DD
+        #[arg(long, value_parser = parse_date_arg)]
+        from: Option<chrono::NaiveDate>,
+        /// Last day to include, YYYY-MM-DD
+        #[arg(long, value_parser = parse_date_arg)]
+        to: Option<chrono::NaiveDate>,
+    },
+    /// Preview importing a CSV or JSON history file, or save it with --commit
+    Import {
+        path: PathBuf,
+        #[arg(long)]
+        commit: bool,
+    },
+    /// Bring the database schema and indexes up to date
+    Migrate,
+    /// Print the all-time summary and the tally of a day
+    Stats {
+        /// Day to show, YYYY-MM-DD; today (UTC) when omitted
+        #[arg(long, value_parser = parse_date_arg)]
+        date: Option<chrono::NaiveDate>,
+    },
+    /// Run console commands in the running bot, e.g. `latebot admin today`; without a
+    /// command, read commands from standard input
+    Admin {
+        /// Admin socket of the bot; admin.socket_path from the config when omitted
+        #[arg(long)]
+        socket: Option<PathBuf>,
+        /// Connect to this port on 127.0.0.1 instead of the socket, sending admin.tcp_token
+        #[arg(long)]
+        port: Option<u16>,
+        /// Command and its arguments, as typed in the console
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// Exit successfully if the running bot reports it is ready, for Docker's HEALTHCHECK
+    Healthcheck {
+        /// Health server to ask; derived from health.bind_address when omitted
+        #[arg(long)]
+        address: Option<String>,
+        /// Only check that the process is up (/healthz) rather than ready (/readyz)
+        #[arg(long)]
+        live: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Write a default config file with every setting documented
+    Init {
+        #[arg(long, value_enum, default_value_t = ConfigFormatArg::Toml)]
+        format: ConfigFormatArg,
+        /// File to write; config.<format> when omitted, `-` for standard output
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Replace the file if it exists
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    /// Log specification understood by `flexi_logger`
+    pub fn spec(self) -> &'static str {
+        match self {
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Trace => "trace",
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum FormatArg {
+    Csv,
+    Json,
+}
+
+impl From<FormatArg> for HistoryFormat {
+    fn from(format: FormatArg) -> Self {
+        match format {
+            FormatArg::Csv => HistoryFormat::Csv,
+            FormatArg::Json => HistoryFormat::Json,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum ConfigFormatArg {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl From<ConfigFormatArg> for ConfigFormat {
+    fn from(format: ConfigFormatArg) -> Self {
+        match format {
+            ConfigFormatArg::Json => ConfigFormat::Json,
+            ConfigFormatArg::Toml => ConfigFormat::Toml,
+            ConfigFormatArg::Yaml => ConfigFormat::Yaml,
+        }
+    }
+}
+
+/// Config files looked for when `--config` isn't given, in order
+const CONFIG_CANDIDATES: &[&str] = &["config.json", "config.toml", "config.yaml", "config.yml"];
+
+impl Cli {
+    /// The config file to use: `--config`, else the first candidate that exists
+    pub fn config_path(&self) -> PathBuf {
+        self.config.clone().unwrap_or_else(|| {
+            CONFIG_CANDIDATES
+                .iter()
+                .map(PathBuf::from)
+                .find(|path| path.exists())
+                .unwrap_or_else(|| PathBuf::from(CONFIG_CANDIDATES[0]))
+        })
+    }
+
+    /// Whether the config file must exist: only a discovered file may be missing, a path
+    /// given with `--config` must not silently fall back to defaults
+    pub fn config_required(&self) -> bool {
+        self.config.is_some()
+    }
+}
+
+/// Runs `config` subcommands, which need neither the database nor a valid configuration
+pub fn run_config_command(command: ConfigCommand) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        ConfigCommand::Init { format, output, force } => {
+            let format = ConfigFormat::from(format);
+            let contents = render_default(format);
+            let path = output.unwrap_or_else(|| PathBuf::from(format!("config.{}", format.extension())));
+
+            if path.as_os_str() == "-" {
+                print!("{}", contents);
+                return Ok(());
+            }
+            if path.exists() && !force {
+                return Err(format!("{} already exists, pass --force to replace it", path.display()).into());
+            }
+            std::fs::write(&path, contents)?;
+            eprintln!("Wrote {}", path.display());
+        }
+    }
+    Ok(())
+}
+
+fn parse_date_arg(value: &str) -> Result<chrono::NaiveDate, String> {
+    crate::history::parse_date(value).ok_or_else(|| format!("'{}' is not a YYYY-MM-DD date", value))
+}
@@ -1,51 +1,297 @@
+use arc_swap::ArcSwap;
+use chrono::FixedOffset;
 use serde::{Deserialize, Serialize};
-use std::fs::File;
-use std::io::BufReader;
+use std::fs;
+use std::io;
 use std::path::Path;
+use std::sync::Arc;
 
+use crate::charts::config::ChartsConfig;
+use crate::console::config::AdminConfig;
+use crate::health::config::HealthConfig;
+use crate::localization::Lang;
+use crate::milestones::config::{default_milestones, MilestoneRule};
+use crate::scheduler::config::SchedulerConfig;
 use crate::securiy::config::BotSecurityConfig;
 
+pub mod error;
+pub mod format;
+pub mod layers;
+pub mod locate;
+pub mod reload;
+pub mod secret;
+pub mod template;
+pub mod validate;
+
+pub use error::ConfigError;
+use format::ConfigFormat;
+use layers::{Source, Sources};
+use secret::Secret;
+
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     pub bot: BotConfig,
     pub database: DatabaseConfig,
     pub security: BotSecurityConfig,
+    #[serde(default)]
+    pub scheduler: SchedulerConfig,
+    #[serde(default)]
+    pub charts: ChartsConfig,
+    #[serde(default)]
+    pub admin: AdminConfig,
+    #[serde(default)]
+    pub health: HealthConfig,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct BotConfig {
     pub target_name: String,
     pub notification_chat_id: i64,
     pub ping_user: String,
+    /// Language used when neither the chat nor the user has one the bot supports
+    #[serde(default = "default_language")]
+    pub default_language: String,
+    /// Announcements posted to `notification_chat_id` as votes come in
+    #[serde(default = "default_milestones")]
+    pub milestones: Vec<MilestoneRule>,
+    /// How the bot asks for votes: inline buttons or a native Telegram poll
+    #[serde(default)]
+    pub vote_mode: VoteMode,
+    /// Telegram user ids allowed to run admin commands such as `/export`
+    #[serde(default)]
+    pub admins: Vec<i64>,
+    /// Offset of the team's time zone from UTC, e.g. `180` for Moscow.
+    /// Used for schedules and for times shown on charts
+    #[serde(default)]
+    pub utc_offset_minutes: i32,
+    /// Telegram bot token. Falls back to `token_file`, then to the `TELOXIDE_TOKEN` and
+    /// `TELOXIDE_TOKEN_FILE` environment variables
+    #[serde(default)]
+    pub token: Option<Secret>,
+    /// File holding the bot token, e.g. a Docker secret under `/run/secrets`
+    #[serde(default)]
+    pub token_file: Option<String>,
+}
+
+/// `BotConfig` shared with the handlers and jobs; a config reload swaps in a new one
+pub type SharedBotConfig = Arc<ArcSwap<BotConfig>>;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VoteMode {
+    #[default]
+    Buttons,
+    Poll,
+}
+
+fn default_language() -> String {
+    "ru".to_string()
+}
+
+impl BotConfig {
+    /// Parsed `default_language`, falling back to Russian for unsupported codes
+    pub fn default_lang(&self) -> Lang {
+        Lang::from_code(&self.default_language).unwrap_or_default()
+    }
+
+    pub fn is_admin(&self, user_id: i64) -> bool {
+        self.admins.contains(&user_id)
+    }
+
+    /// The team's time zone, or `None` if `utc_offset_minutes` is out of range
+    pub fn utc_offset(&self) -> Option<FixedOffset> {
+        FixedOffset::east_opt(self.utc_offset_minutes.checked_mul(60)?)
+    }
+
+    /// The bot token from the first place that has one: `token`, `token_file`,
+    /// `TELOXIDE_TOKEN`, `TELOXIDE_TOKEN_FILE`
+    pub fn resolve_token(&self) -> Result<Secret, ConfigError> {
+        if let Some(token) = &self.token {
+            return Ok(token.clone());
+        }
+        if let Some(path) = &self.token_file {
+            return read_secret_file("bot.token_file", path).map(Secret::new);
+        }
+        if let Some(token) = std::env::var_os(TOKEN_VARIABLE).and_then(|token| token.into_string().ok()) {
+            return Ok(Secret::new(token));
+        }
+        if let Some(path) = std::env::var_os(format!("{}_FILE", TOKEN_VARIABLE)) {
+            let path = path.to_string_lossy();
+            return read_secret_file(&format!("{}_FILE", TOKEN_VARIABLE), &path).map(Secret::new);
+        }
+        Err(ConfigError::MissingToken)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct DatabaseConfig {
-    pub connection_uri: String,
+    /// May contain credentials, so it is redacted from logs and debug output
+    pub connection_uri: Secret,
+    /// How long startup waits for the database before going on in degraded mode
+    #[serde(default = "default_startup_timeout_seconds")]
+    pub startup_timeout_seconds: u64,
+    /// Upper bound of the exponential backoff between startup connection attempts
+    #[serde(default = "default_retry_max_seconds")]
+    pub retry_max_seconds: u64,
+    /// How often the background health check pings the database
+    #[serde(default = "default_health_check_interval_seconds")]
+    pub health_check_interval_seconds: u64,
+    /// How long a single operation waits for a reachable server before failing
+    #[serde(default = "default_server_selection_timeout_seconds")]
+    pub server_selection_timeout_seconds: u64,
+    /// File votes are queued in while the database is unreachable
+    #[serde(default = "default_vote_journal_path")]
+    pub vote_journal_path: String,
+    /// How long today's cached tally is trusted before checking for writes from other
+    /// instances; `0` disables the cache
+    #[serde(default = "default_today_cache_ttl_ms")]
+    pub today_cache_ttl_ms: u64,
 }
 
-impl Config {
-    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
-        // Open the file in read-only mode
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
-
-        // Read the JSON contents into the Config struct
-        let config = serde_json::from_reader(reader)?;
-        Ok(config)
+fn default_startup_timeout_seconds() -> u64 {
+    120
+}
+
+fn default_retry_max_seconds() -> u64 {
+    30
+}
+
+fn default_health_check_interval_seconds() -> u64 {
+    10
+}
+
+fn default_server_selection_timeout_seconds() -> u64 {
+    5
+}
+
+fn default_vote_journal_path() -> String {
+    "vote-journal.jsonl".to_string()
+}
+
+fn default_today_cache_ttl_ms() -> u64 {
+    2000
+}
+
+/// Environment variable teloxide reads the token from, still honoured for compatibility
+const TOKEN_VARIABLE: &str = "TELOXIDE_TOKEN";
+
+/// Reads a secret from a file, without the trailing newline most editors and `echo` add.
+/// `name` is the setting or variable the path came from
+pub fn read_secret_file(name: &str, path: &str) -> Result<String, ConfigError> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(contents.trim_end_matches(['\r', '\n']).to_string()),
+        Err(source) => Err(ConfigError::SecretFile {
+            name: name.to_string(),
+            path: path.to_string(),
+            source,
+        }),
     }
+}
 
-    pub fn load_or_default<P: AsRef<Path>>(path: P) -> Self {
-        match Self::load(path) {
-            Ok(config) => {
-                log::info!("Configuration loaded successfully");
-                config
-            }
+/// Settings whose built-in values are only placeholders; they have to be configured
+/// unless defaults are explicitly allowed
+const REQUIRED: &[&str] = &["database.connection_uri"];
+
+impl Config {
+    /// Loads defaults, then the file at `path`, then `LATEBOT_*` environment variables, and
+    /// validates the result. The file may be JSON, TOML (`.toml`) or YAML (`.yaml`, `.yml`).
+    /// A missing file is an error if `file_required` is set, e.g. because the user named it;
+    /// otherwise the file layer is skipped.
+    ///
+    /// Settings listed in [`REQUIRED`] must come from the file or the environment unless
+    /// `allow_defaults` is set. Returns where each setting came from along with the config.
+    pub fn load<P: AsRef<Path>>(
+        path: P,
+        file_required: bool,
+        allow_defaults: bool,
+    ) -> Result<(Self, Sources), ConfigError> {
+        let path = path.as_ref();
+        let display_path = path.display().to_string();
+
+        let mut value = serde_json::to_value(Self::default()).expect("default config serializes");
+        let mut sources = Sources::new();
+        layers::record_sources(&value, "", &Source::Default, &mut sources);
+
+        let text = match fs::read_to_string(path) {
+            Ok(text) => Some(text),
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !file_required => None,
             Err(e) => {
-                log::warn!("Failed to load configuration: {}. Using default values", e);
-                Self::default()
+                return Err(ConfigError::Read {
+                    path: display_path,
+                    source: e,
+                })
+            }
+        };
+        let format = ConfigFormat::from_path(path);
+        if let Some(text) = &text {
+            let file = format.parse(text).map_err(|e| ConfigError::Syntax {
+                path: display_path.clone(),
+                line: e.line,
+                column: e.column,
+                message: e.message,
+            })?;
+            // An empty YAML document parses as null
+            if !file.is_null() {
+                layers::merge(&mut value, file, "", &Source::File(display_path.clone()), &mut sources);
             }
         }
+
+        // Variables that aren't valid Unicode can't name a setting anyway
+        let vars = std::env::vars_os()
+            .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)));
+        let unknown = layers::apply_env(&mut value, vars, &mut sources)?;
+        if !unknown.is_empty() {
+            return Err(ConfigError::UnknownVariables(unknown));
+        }
+
+        if !allow_defaults {
+            let missing = REQUIRED
+                .iter()
+                .find(|field| sources.get(**field).is_none_or(|source| *source == Source::Default));
+            if let Some(field) = missing {
+                return Err(ConfigError::Missing {
+                    field: field.to_string(),
+                    path: display_path,
+                });
+            }
+        }
+
+        let config: Self = serde_path_to_error::deserialize(value).map_err(|e| {
+            let field = e.path().to_string();
+            let source = layers::source_of(&sources, &field);
+            // Positions of values are only tracked down in JSON files
+            let position = match (&source, &text) {
+                (Source::File(_), Some(text)) if format == ConfigFormat::Json => locate::locate(text, &field),
+                _ => None,
+            };
+            ConfigError::Field {
+                field,
+                source,
+                position,
+                message: e.into_inner().to_string(),
+            }
+        })?;
+
+        let problems = validate::check(&config);
+        if !problems.is_empty() {
+            return Err(ConfigError::Invalid(problems));
+        }
+        config.register_secrets();
+        Ok((config, sources))
+    }
+
+    /// Makes the log format redact the credentials in this config
+    fn register_secrets(&self) {
+        if let Some(token) = &self.bot.token {
+            secret::register(token.expose());
+        }
+        secret::register_uri_password(self.database.connection_uri.expose());
+        if let Some(token) = &self.admin.tcp_token {
+            secret::register(token.expose());
+        }
     }
 }
 
@@ -56,11 +302,28 @@ impl Default for Config {
                 target_name: "Не указан".to_string(),
                 notification_chat_id: 0,
                 ping_user: "@Test".to_string(),
+                default_language: default_language(),
+                milestones: default_milestones(),
+                vote_mode: VoteMode::default(),
+                admins: Vec::new(),
+                utc_offset_minutes: 0,
+                token: None,
+                token_file: None,
             },
             database: DatabaseConfig {
-                connection_uri: "mongodb://10.10.10.10:27017/".to_string(),
+                connection_uri: Secret::new("mongodb://10.10.10.10:27017/"),
+                startup_timeout_seconds: default_startup_timeout_seconds(),
+                retry_max_seconds: default_retry_max_seconds(),
+                health_check_interval_seconds: default_health_check_interval_seconds(),
+                server_selection_timeout_seconds: default_server_selection_timeout_seconds(),
+                vote_journal_path: default_vote_journal_path(),
+                today_cache_ttl_ms: default_today_cache_ttl_ms(),
             },
             security: BotSecurityConfig::default(),
+            scheduler: SchedulerConfig::default(),
+            charts: ChartsConfig::default(),
+            admin: AdminConfig::default(),
+            health: HealthConfig::default(),
         }
     }
 }
@@ -0,0 +1,101 @@
+use std::fmt;
+use std::io;
+
+use super::layers::{Source, ENV_PREFIX};
+
+/// Why the configuration can't be used
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The config file exists but can't be read
+    Read { path: String, source: io::Error },
+    /// The config file isn't valid in its format: JSON, TOML or YAML
+    Syntax {
+        path: String,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// A setting has a value of the wrong type or isn't a known setting
+    Field {
+        field: String,
+        source: Source,
+        /// Line and column in the config file, when the value came from there
+        position: Option<(usize, usize)>,
+        message: String,
+    },
+    /// A setting without a usable built-in value isn't configured
+    Missing { field: String, path: String },
+    /// A file a secret should be read from can't be read
+    SecretFile {
+        /// Setting or environment variable naming the file
+        name: String,
+        path: String,
+        source: io::Error,
+    },
+    /// No bot token is configured anywhere
+    MissingToken,
+    /// `LATEBOT_*` variables that don't name any setting
+    UnknownVariables(Vec<String>),
+    /// Settings that parse but don't make sense
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read { path, source } => write!(f, "failed to read {}: {}", path, source),
+            ConfigError::Syntax {
+                path,
+                line,
+                column,
+                message,
+            } => write!(f, "{}:{}:{}: {}", path, line, column, message),
+            ConfigError::Field {
+                field,
+                source,
+                position,
+                message,
+            } => match (source, position) {
+                (Source::File(path), Some((line, column))) => {
+                    write!(f, "{}:{}:{}: {}: {}", path, line, column, field, message)
+                }
+                (source, _) => write!(f, "{} (from {}): {}", field, source, message),
+            },
+            ConfigError::Missing { field, path } => write!(
+                f,
+                "{} is not set; set it in {} or with {}{}, or pass --allow-defaults to use the built-in value",
+                field,
+                path,
+                ENV_PREFIX,
+                field.replace('.', "_").to_uppercase()
+            ),
+            ConfigError::SecretFile { name, path, source } => {
+                write!(f, "failed to read {} given by {}: {}", path, name, source)
+            }
+            ConfigError::MissingToken => write!(
+                f,
+                "the bot token is not set; set bot.token or bot.token_file, or the TELOXIDE_TOKEN \
+                 or TELOXIDE_TOKEN_FILE environment variable"
+            ),
+            ConfigError::UnknownVariables(names) => {
+                write!(f, "unknown setting in environment: {}", names.join(", "))
+            }
+            ConfigError::Invalid(problems) => {
+                write!(f, "invalid configuration:")?;
+                for problem in problems {
+                    write!(f, "\n  - {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Read { source, .. } | ConfigError::SecretFile { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
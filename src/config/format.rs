@@ -0,0 +1,73 @@
+//! Config file formats. All of them are parsed into a `serde_json::Value` so that layering
+//! and validation don't depend on the format.
+
+use std::path::Path;
+
+use serde_json::Value;
+
+use super::locate::line_column;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+/// Where and why a config file failed to parse
+pub struct SyntaxError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl ConfigFormat {
+    /// Format given by the file extension; anything unknown is read as JSON
+    pub fn from_path(path: &Path) -> Self {
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        match extension.map(str::to_lowercase).as_deref() {
+            Some("toml") => ConfigFormat::Toml,
+            Some("yaml" | "yml") => ConfigFormat::Yaml,
+            _ => ConfigFormat::Json,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ConfigFormat::Json => "json",
+            ConfigFormat::Toml => "toml",
+            ConfigFormat::Yaml => "yaml",
+        }
+    }
+
+    pub fn parse(self, text: &str) -> Result<Value, SyntaxError> {
+        match self {
+            ConfigFormat::Json => serde_json::from_str(text).map_err(|e| SyntaxError {
+                line: e.line(),
+                column: e.column(),
+                message: without_position(&e.to_string(), e.line(), e.column()),
+            }),
+            ConfigFormat::Toml => toml::from_str(text).map_err(|e| {
+                let (line, column) = e.span().map_or((0, 0), |span| line_column(text, span.start));
+                SyntaxError {
+                    line,
+                    column,
+                    message: e.message().trim().replace('\n', "; "),
+                }
+            }),
+            ConfigFormat::Yaml => serde_yaml::from_str(text).map_err(|e| {
+                let (line, column) = e.location().map_or((0, 0), |location| (location.line(), location.column()));
+                SyntaxError {
+                    line,
+                    column,
+                    message: without_position(&e.to_string(), line, column),
+                }
+            }),
+        }
+    }
+}
+
+/// Drops the parser's own "at line L column C", the position is reported separately
+fn without_position(message: &str, line: usize, column: usize) -> String {
+    message.replacen(&format!(" at line {} column {}", line, column), "", 1)
+}
@@ -0,0 +1,215 @@
+//! Layered configuration: built-in defaults, then the config file, then `LATEBOT_*`
+//! environment variables.
+//!
+//! Layers are merged as JSON values before being deserialized, so a file or the environment
+//! only needs to mention the fields it changes. An environment variable names a field by its
+//! path in upper case with `_` between the parts, e.g. `LATEBOT_BOT_TARGET_NAME` or
+//! `LATEBOT_SECURITY_REQUEST_LIMIT`. Lists take comma-separated items
+//! (`LATEBOT_BOT_ADMINS=1,2`) or a JSON array, and any field may be given as JSON.
+//! Appending `_FILE` to a variable reads the value from the named file instead, which is
+//! how Docker secrets are passed, e.g. `LATEBOT_DATABASE_CONNECTION_URI_FILE`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde_json::{Map, Value};
+
+use super::ConfigError;
+
+/// Prefix of the environment variables that override config fields
+pub const ENV_PREFIX: &str = "LATEBOT_";
+
+/// Where the effective value of a config field came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    File(String),
+    Env(String),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => f.write_str("defaults"),
+            Source::File(path) => f.write_str(path),
+            Source::Env(name) => write!(f, "environment variable {}", name),
+        }
+    }
+}
+
+/// Source of every leaf field, keyed by its dotted path such as `bot.target_name`
+pub type Sources = BTreeMap<String, Source>;
+
+/// Records `source` for every leaf of `value`; objects are walked, anything else is a leaf
+pub fn record_sources(value: &Value, path: &str, source: &Source, sources: &mut Sources) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                record_sources(value, &join(path, key), source, sources);
+            }
+        }
+        _ => {
+            sources.insert(path.to_string(), source.clone());
+        }
+    }
+}
+
+/// Merges `overlay` into `base`: objects are merged key by key, anything else is replaced
+pub fn merge(base: &mut Value, overlay: Value, path: &str, source: &Source, sources: &mut Sources) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                let path = join(path, &key);
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value, &path, source, sources),
+                    None => {
+                        record_sources(&value, &path, source, sources);
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => {
+            record_sources(&overlay, path, source, sources);
+            *base = overlay;
+        }
+    }
+}
+
+/// Applies `LATEBOT_*` variables from `vars` to `config`.
+///
+/// Returns the names of variables that don't match any field.
+pub fn apply_env(
+    config: &mut Value,
+    vars: impl IntoIterator<Item = (String, String)>,
+    sources: &mut Sources,
+) -> Result<Vec<String>, ConfigError> {
+    let mut unknown = Vec::new();
+    let mut vars: Vec<(String, String)> = vars
+        .into_iter()
+        .filter(|(name, _)| name.starts_with(ENV_PREFIX))
+        .collect();
+    // Apply in a stable order so the outcome doesn't depend on the environment's ordering
+    vars.sort();
+
+    for (name, raw) in vars {
+        let field = &name[ENV_PREFIX.len()..];
+        // A setting whose own name ends in `_FILE` (such as `bot.token_file`) takes precedence
+        let (field, raw) = match field.strip_suffix("_FILE") {
+            Some(target) if find_field(config, field).is_none() => {
+                (target, super::read_secret_file(&name, &raw)?)
+            }
+            _ => (field, raw),
+        };
+
+        let Some((path, slot)) = find_field(config, field) else {
+            unknown.push(name);
+            continue;
+        };
+        *slot = parse_env_value(slot, &raw);
+        record_sources(slot, &path, &Source::Env(name.clone()), sources);
+    }
+    Ok(unknown)
+}
+
+/// Finds the field named by the upper-case `name`, e.g. `SECURITY_REQUEST_LIMIT`.
+///
+/// Keys may contain `_` themselves, so at each level the longest matching key wins.
+fn find_field<'a>(config: &'a mut Value, name: &str) -> Option<(String, &'a mut Value)> {
+    let mut path = String::new();
+    let mut current = config;
+    let mut rest = name;
+
+    loop {
+        let Value::Object(map) = current else {
+            return None;
+        };
+        let key = longest_matching_key(map, rest)?;
+        rest = rest[key.len()..].strip_prefix('_').unwrap_or_default();
+        path = join(&path, &key);
+        current = map.get_mut(&key)?;
+
+        if rest.is_empty() {
+            return Some((path, current));
+        }
+    }
+}
+
+fn longest_matching_key(map: &Map<String, Value>, name: &str) -> Option<String> {
+    map.keys()
+        .filter(|key| {
+            let key = key.to_uppercase();
+            name == key || name.strip_prefix(&key).is_some_and(|rest| rest.starts_with('_'))
+        })
+        .max_by_key(|key| key.len())
+        .cloned()
+}
+
+/// Interprets a variable according to the type of the value it replaces
+fn parse_env_value(current: &Value, raw: &str) -> Value {
+    let trimmed = raw.trim();
+    let as_json = || serde_json::from_str::<Value>(trimmed).ok();
+
+    match current {
+        Value::String(_) => Value::String(raw.to_string()),
+        // Optional text fields default to null; an empty variable keeps them unset
+        Value::Null if trimmed.is_empty() => Value::Null,
+        Value::Null => as_json()
+            .filter(|value| value.is_object() || value.is_array())
+            .unwrap_or_else(|| Value::String(raw.to_string())),
+        Value::Array(_) if trimmed.starts_with('[') => {
+            as_json().unwrap_or_else(|| Value::String(raw.to_string()))
+        }
+        Value::Array(_) => Value::Array(
+            trimmed
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| {
+                    serde_json::from_str(item).unwrap_or_else(|_| Value::String(item.to_string()))
+                })
+                .collect(),
+        ),
+        // Numbers, booleans and objects are written as JSON; anything unparsable is kept as
+        // text so deserialization reports the field with a type error
+        _ => as_json().unwrap_or_else(|| Value::String(raw.to_string())),
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+/// Source of the setting at `path`; elements of a list share the list's source
+pub fn source_of(sources: &Sources, path: &str) -> Source {
+    let mut path = path;
+    loop {
+        if let Some(source) = sources.get(path) {
+            return source.clone();
+        }
+        match path.rfind(['.', '[']) {
+            Some(end) => path = &path[..end],
+            None => return Source::Default,
+        }
+    }
+}
+
+/// Describes where the effective settings came from, one line per source
+pub fn describe_sources(sources: &Sources) -> Vec<String> {
+    let mut by_source: Vec<(&Source, Vec<&str>)> = Vec::new();
+    for (path, source) in sources {
+        match by_source.iter_mut().find(|(known, _)| *known == source) {
+            Some((_, paths)) => paths.push(path),
+            None => by_source.push((source, vec![path])),
+        }
+    }
+
+    by_source
+        .into_iter()
+        .map(|(source, paths)| format!("from {}: {}", source, paths.join(", ")))
+        .collect()
+}
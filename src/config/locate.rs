@@ -0,0 +1,145 @@
+//! Finding where a setting is written in the config file.
+//!
+//! Settings are merged as `serde_json::Value`s, which don't remember positions, so an error
+//! in a value that came from the file is traced back to its line and column by walking the
+//! file text along the setting's path.
+
+/// Part of a path such as `security.whitelist[1]`
+enum Segment<'a> {
+    Key(&'a str),
+    Index(usize),
+}
+
+/// Returns the 1-based line and column of the value at `path` in the JSON `text`
+pub fn locate(text: &str, path: &str) -> Option<(usize, usize)> {
+    let segments = parse_path(path)?;
+    let offset = Walker { text: text.as_bytes(), pos: 0 }.find(&segments)?;
+    Some(line_column(text, offset))
+}
+
+fn parse_path(path: &str) -> Option<Vec<Segment<'_>>> {
+    let mut segments = Vec::new();
+    for part in path.split('.').filter(|part| !part.is_empty()) {
+        let (key, mut indices) = match part.find('[') {
+            Some(start) => part.split_at(start),
+            None => (part, ""),
+        };
+        if !key.is_empty() {
+            segments.push(Segment::Key(key));
+        }
+        while let Some(rest) = indices.strip_prefix('[') {
+            let end = rest.find(']')?;
+            segments.push(Segment::Index(rest[..end].parse().ok()?));
+            indices = &rest[end + 1..];
+        }
+    }
+    Some(segments)
+}
+
+/// 1-based line and column of the byte `offset` in `text`
+pub fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    (line, before[line_start..].chars().count() + 1)
+}
+
+/// Minimal scanner over text that is already known to be valid JSON
+struct Walker<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl Walker<'_> {
+    fn find(&mut self, segments: &[Segment]) -> Option<usize> {
+        self.skip_whitespace();
+        let Some((segment, rest)) = segments.split_first() else {
+            return Some(self.pos);
+        };
+
+        match segment {
+            Segment::Key(wanted) => {
+                self.expect(b'{')?;
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.skip_whitespace();
+                    self.expect(b':')?;
+                    if key == *wanted {
+                        return self.find(rest);
+                    }
+                    self.skip_value()?;
+                    self.skip_whitespace();
+                    self.expect(b',')?;
+                }
+            }
+            Segment::Index(wanted) => {
+                self.expect(b'[')?;
+                for _ in 0..*wanted {
+                    self.skip_value()?;
+                    self.skip_whitespace();
+                    self.expect(b',')?;
+                }
+                self.find(rest)
+            }
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.text.get(self.pos).is_some_and(u8::is_ascii_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Option<()> {
+        (self.text.get(self.pos) == Some(&byte)).then(|| self.pos += 1)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let start = self.pos;
+        self.expect(b'"')?;
+        loop {
+            match self.text.get(self.pos)? {
+                b'\\' => self.pos += 2,
+                b'"' => break,
+                _ => self.pos += 1,
+            }
+        }
+        self.pos += 1;
+        serde_json::from_slice(&self.text[start..self.pos]).ok()
+    }
+
+    fn skip_value(&mut self) -> Option<()> {
+        self.skip_whitespace();
+        match self.text.get(self.pos)? {
+            b'"' => self.string().map(drop),
+            b'{' | b'[' => {
+                let mut depth = 0usize;
+                loop {
+                    match self.text.get(self.pos)? {
+                        b'"' => {
+                            self.string()?;
+                            continue;
+                        }
+                        b'{' | b'[' => depth += 1,
+                        b'}' | b']' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                self.pos += 1;
+                                return Some(());
+                            }
+                        }
+                        _ => {}
+                    }
+                    self.pos += 1;
+                }
+            }
+            _ => {
+                while self.text.get(self.pos).is_some_and(|byte| !b",}] \t\r\n".contains(byte)) {
+                    self.pos += 1;
+                }
+                Some(())
+            }
+        }
+    }
+}
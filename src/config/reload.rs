@@ -0,0 +1,217 @@
+//! Applying config changes while the bot runs.
+//!
+//! The config is reloaded when the file changes, on SIGHUP and with the console `reload`
+//! command. A config that fails validation is rejected and the running one is kept. The
+//! `bot` and `security` sections are swapped in atomically; the others are only read at
+//! startup, so changing them logs that a restart is needed. The scheduler also reads
+//! `bot.notification_chat_id` and `bot.utc_offset_minutes` only at startup, and the bot
+//! token is only used to log in.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use serde_json::Value;
+
+use crate::securiy::manager::SecurityManager;
+
+use super::{Config, ConfigError, SharedBotConfig};
+
+/// How often the config file's modification time is checked
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Sections that take effect without a restart
+const LIVE_SECTIONS: &[&str] = &["bot", "security"];
+
+pub struct ConfigReloader {
+    path: PathBuf,
+    file_required: bool,
+    allow_defaults: bool,
+    bot_config: SharedBotConfig,
+    security_manager: Arc<SecurityManager>,
+    /// Settings in effect, serialized to tell which sections a reload changes
+    applied: Mutex<Value>,
+    /// Modification time of the file when it was last loaded successfully
+    modified: Mutex<Option<SystemTime>>,
+}
+
+impl ConfigReloader {
+    /// `config` is the configuration the bot was started with, loaded from `path`; the
+    /// other arguments are the ones it was loaded with
+    pub fn new(
+        path: PathBuf,
+        file_required: bool,
+        allow_defaults: bool,
+        config: &Config,
+        bot_config: SharedBotConfig,
+        security_manager: Arc<SecurityManager>,
+    ) -> Self {
+        let modified = modified_time(&path);
+        Self {
+            path,
+            file_required,
+            allow_defaults,
+            bot_config,
+            security_manager,
+            applied: Mutex::new(serde_json::to_value(config).unwrap_or_default()),
+            modified: Mutex::new(modified),
+        }
+    }
+
+    /// Loads and validates the config again and applies what changed.
+    ///
+    /// Returns the names of the changed sections, including those that need a restart.
+    /// Reads the file with blocking calls; async code goes through [`Self::reload_and_log`].
+    pub fn reload(&self) -> Result<Vec<String>, ConfigError> {
+        // Taken before reading, so a save made during the load is picked up by the next check
+        let modified = modified_time(&self.path);
+        let (config, _) = Config::load(&self.path, self.file_required, self.allow_defaults)?;
+        *self.modified.lock().unwrap_or_else(|e| e.into_inner()) = modified;
+        let new = serde_json::to_value(&config).unwrap_or_default();
+
+        let mut applied = self.applied.lock().unwrap_or_else(|e| e.into_inner());
+        let changed: Vec<String> = new
+            .as_object()
+            .into_iter()
+            .flatten()
+            .filter(|(section, value)| applied.get(section.as_str()) != Some(*value))
+            .map(|(section, _)| section.clone())
+            .collect();
+
+        for section in &changed {
+            match section.as_str() {
+                "bot" => {
+                    if applied["bot"]["token"] != new["bot"]["token"]
+                        || applied["bot"]["token_file"] != new["bot"]["token_file"]
+                    {
+                        log::warn!("Changes to the bot token take effect after a restart");
+                    }
+                    self.bot_config.store(Arc::new(config.bot.clone()));
+                }
+                "security" => self.security_manager.update_config(config.security.clone()),
+                _ => log::warn!("Changes to '{}' settings take effect after a restart", section),
+            }
+        }
+        *applied = new;
+        Ok(changed)
+    }
+
+    /// Reloads on the blocking thread pool and logs the outcome; `trigger` says what asked
+    /// for the reload
+    pub async fn reload_and_log(self: &Arc<Self>, trigger: &str) -> Result<Vec<String>, ConfigError> {
+        let result = self.reload_blocking().await;
+        log_outcome(trigger, &result);
+        result
+    }
+
+    async fn reload_blocking(self: &Arc<Self>) -> Result<Vec<String>, ConfigError> {
+        let reloader = self.clone();
+        tokio::task::spawn_blocking(move || reloader.reload())
+            .await
+            .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+    }
+
+    /// Modification time of the file if it differs from the one last loaded
+    async fn changed_time(&self) -> Option<SystemTime> {
+        let current = tokio::fs::metadata(&self.path).await.and_then(|metadata| metadata.modified()).ok()?;
+        let loaded = *self.modified.lock().unwrap_or_else(|e| e.into_inner());
+        (loaded != Some(current)).then_some(current)
+    }
+}
+
+fn log_outcome(trigger: &str, result: &Result<Vec<String>, ConfigError>) {
+    match result {
+        Ok(changed) if changed.is_empty() => log::info!("Config reloaded ({}), nothing changed", trigger),
+        Ok(changed) => {
+            let live: Vec<&str> = changed
+                .iter()
+                .map(String::as_str)
+                .filter(|section| LIVE_SECTIONS.contains(section))
+                .collect();
+            if live.is_empty() {
+                log::info!("Config reloaded ({}), nothing applied live", trigger);
+            } else {
+                log::info!("Config reloaded ({}), applied: {}", trigger, live.join(", "));
+            }
+        }
+        Err(e) => log::error!("Config reload ({}) rejected, keeping the running config: {}", trigger, e),
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Spawns the tasks reloading the config when the file changes or on SIGHUP
+pub fn start_config_watcher(reloader: Arc<ConfigReloader>) {
+    let watcher = reloader.clone();
+    tokio::spawn(async move {
+        // A failed load is retried on every check, since it may have caught a save halfway,
+        // but only reported again once the file changes
+        let mut failed_at = None;
+        loop {
+            tokio::time::sleep(WATCH_INTERVAL).await;
+            let Some(modified) = watcher.changed_time().await else {
+                continue;
+            };
+            let result = watcher.reload_blocking().await;
+            if result.is_ok() || failed_at != Some(modified) {
+                log_outcome("file changed", &result);
+            }
+            failed_at = result.is_err().then_some(modified);
+        }
+    });
+
+    #[cfg(unix)]
+    tokio::spawn(async move {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hangups = match signal(SignalKind::hangup()) {
+            Ok(hangups) => hangups,
+            Err(e) => return log::warn!("Config reload on SIGHUP is unavailable: {}", e),
+        };
+        while hangups.recv().await.is_some() {
+            let _ = reloader.reload_and_log("SIGHUP").await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use arc_swap::ArcSwap;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn failed_load_is_retried_until_one_succeeds() {
+        let dir = std::env::temp_dir().join(format!("latebot-reload-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.json");
+        fs::write(&path, "{}").unwrap();
+
+        let config = Config::load(&path, true, true).unwrap().0;
+        let reloader = Arc::new(ConfigReloader::new(
+            path.clone(),
+            true,
+            true,
+            &config,
+            Arc::new(ArcSwap::from_pointee(config.bot.clone())),
+            Arc::new(SecurityManager::new(config.security.clone()).await),
+        ));
+        assert!(reloader.changed_time().await.is_none());
+
+        // A save caught halfway; the modification time may not change once it is complete
+        fs::write(&path, r#"{"bot": {"target_name": "#).unwrap();
+        assert!(reloader.changed_time().await.is_some());
+        assert!(reloader.reload_and_log("test").await.is_err());
+        assert!(reloader.changed_time().await.is_some());
+
+        fs::write(&path, r#"{"bot": {"target_name": "Ivan"}}"#).unwrap();
+        assert_eq!(reloader.reload_and_log("test").await.unwrap(), vec!["bot"]);
+        assert_eq!(reloader.bot_config.load().target_name, "Ivan");
+        assert!(reloader.changed_time().await.is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
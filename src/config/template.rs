@@ -0,0 +1,240 @@
+//! Default config file written by `latebot config init`.
+//!
+//! Values come from `Config::default()`; the comments are kept here because serde can't
+//! see doc comments. JSON has no comments, so a JSON template only has the values.
+
+use serde_json::Value;
+
+use super::format::ConfigFormat;
+use super::Config;
+
+/// What the template shows for a setting
+enum Sample {
+    /// The built-in value
+    Default,
+    /// This value instead of the built-in one, written as JSON
+    Value(&'static str),
+    /// The setting left out, with this example value in a comment, written as JSON
+    Commented(&'static str),
+}
+
+struct Section {
+    name: &'static str,
+    doc: &'static str,
+    fields: &'static [(&'static str, &'static str, Sample)],
+}
+
+const HEADER: &str = "latebot configuration.
+Any setting can be overridden with a LATEBOT_<SECTION>_<SETTING> environment variable,
+e.g. LATEBOT_SECURITY_REQUEST_LIMIT=20, and LATEBOT_<SECTION>_<SETTING>_FILE reads the
+value from a file. Check the result with `latebot check-config`.";
+
+const SECTIONS: &[Section] = &[
+    Section {
+        name: "bot",
+        doc: "What the bot talks about and who may administer it",
+        fields: &[
+            ("target_name", "Name of the person whose lateness is voted on", Sample::Default),
+            (
+                "notification_chat_id",
+                "Chat for announcements and scheduled posts; 0 disables them",
+                Sample::Default,
+            ),
+            ("ping_user", "Username mentioned by the {ping} placeholder of milestone templates", Sample::Default),
+            ("default_language", "Language used when a chat or user has none the bot supports: ru or en", Sample::Default),
+            ("vote_mode", "How votes are collected: buttons or poll", Sample::Default),
+            ("admins", "Telegram user ids allowed to run admin commands such as /export and /import", Sample::Default),
+            (
+                "utc_offset_minutes",
+                "Offset of the team's time zone from UTC, e.g. 180 for Moscow",
+                Sample::Default,
+            ),
+            (
+                "token",
+                "Telegram bot token; prefer token_file or the TELOXIDE_TOKEN variable to keep it out of this file",
+                Sample::Commented("\"123456789:replace-me\""),
+            ),
+            (
+                "token_file",
+                "File holding the bot token, e.g. a Docker secret",
+                Sample::Commented("\"/run/secrets/bot_token\""),
+            ),
+            (
+                "milestones",
+                "Announcements as votes come in. kind is first_vote, every or at; count is the\nthreshold; template supports {count}, {people}, {voters}, {target} and {ping}",
+                Sample::Default,
+            ),
+        ],
+    },
+    Section {
+        name: "database",
+        doc: "MongoDB connection",
+        fields: &[
+            ("connection_uri", "Where the database is; required", Sample::Value("\"mongodb://localhost:27017/\"")),
+            (
+                "startup_timeout_seconds",
+                "How long startup waits for the database before running in degraded mode",
+                Sample::Default,
+            ),
+            ("retry_max_seconds", "Longest pause between connection attempts at startup", Sample::Default),
+            ("health_check_interval_seconds", "How often the database is pinged", Sample::Default),
+            (
+                "server_selection_timeout_seconds",
+                "How long a single operation waits for a reachable server",
+                Sample::Default,
+            ),
+            ("vote_journal_path", "File votes are queued in while the database is unreachable", Sample::Default),
+            (
+                "today_cache_ttl_ms",
+                "How long today's cached tally is trusted before checking for other instances' writes;\n0 disables the cache",
+                Sample::Default,
+            ),
+        ],
+    },
+    Section {
+        name: "security",
+        doc: "Rate limiting",
+        fields: &[
+            ("request_limit", "Requests a user may make within time_window_seconds", Sample::Default),
+            ("time_window_seconds", "Length of the rate limiting window", Sample::Default),
+            ("ddos_protection_enabled", "Whether rate limiting and the lists below apply at all", Sample::Default),
+            ("whitelist", "Users never rate limited", Sample::Default),
+            (
+                "blacklist",
+                "Users whose requests are always ignored; note why next to each id",
+                Sample::Default,
+            ),
+        ],
+    },
+    Section {
+        name: "scheduler",
+        doc: "Automatic posts to notification_chat_id. Each job takes a cron expression\n(minute hour day-of-month month day-of-week) in the bot's time zone; leave it out to disable the job",
+        fields: &[
+            ("catch_up_minutes", "A run missed by at most this many minutes is still performed", Sample::Default),
+            ("morning_post", "Posts the voting keyboard or poll", Sample::Commented("\"0 9 * * 1-5\"")),
+            (
+                "reminder",
+                "Reminds users who voted on the previous voting day but not today",
+                Sample::Commented("\"30 11 * * 1-5\""),
+            ),
+            ("daily_summary", "Posts the day's result", Sample::Commented("\"0 18 * * 1-5\"")),
+            ("weekly_digest", "Posts the digest of the previous week", Sample::Commented("\"0 10 * * 1\"")),
+            ("monthly_digest", "Posts the digest of the previous month", Sample::Commented("\"0 10 1 * *\"")),
+        ],
+    },
+    Section {
+        name: "charts",
+        doc: "Chart rendering",
+        fields: &[("font_path", "TrueType font for chart text; it must cover Cyrillic", Sample::Default)],
+    },
+    Section {
+        name: "admin",
+        doc: "Remote access to the console commands with `latebot admin`, e.g. through docker exec",
+        fields: &[
+            ("socket_path", "Unix socket the commands are served on; empty disables it", Sample::Default),
+            (
+                "tcp_port",
+                "Port on 127.0.0.1 serving the same commands; 0 disables it. Every local user\nand process can reach it, so it requires tcp_token; prefer the socket on shared hosts",
+                Sample::Default,
+            ),
+            (
+                "tcp_token",
+                "Secret `latebot admin --port` sends before each command; required with tcp_port",
+                Sample::Commented("\"a-long-random-string\""),
+            ),
+        ],
+    },
+    Section {
+        name: "health",
+        doc: "HTTP endpoints for docker-compose and orchestrators: /healthz answers while the process\nruns, /readyz only while the database is reachable and Telegram polling works",
+        fields: &[(
+            "bind_address",
+            "Address to serve them on, e.g. 0.0.0.0:8080; empty disables them",
+            Sample::Default,
+        )],
+    },
+];
+
+/// Renders a default config file in `format`
+pub fn render_default(format: ConfigFormat) -> String {
+    let mut defaults = serde_json::to_value(Config::default()).unwrap_or_default();
+    for section in SECTIONS {
+        for (key, _, sample) in section.fields {
+            if let (Sample::Value(json), Some(value)) = (sample, defaults.pointer_mut(&format!("/{}/{}", section.name, key))) {
+                *value = serde_json::from_str(json).unwrap_or_default();
+            }
+        }
+    }
+
+    match format {
+        ConfigFormat::Json => serde_json::to_string_pretty(&defaults).unwrap_or_default() + "\n",
+        ConfigFormat::Toml | ConfigFormat::Yaml => render_commented(format, &defaults),
+    }
+}
+
+fn render_commented(format: ConfigFormat, defaults: &Value) -> String {
+    let mut out = String::new();
+    push_comment(&mut out, "", HEADER);
+
+    for section in SECTIONS {
+        out.push('\n');
+        push_comment(&mut out, "", section.doc);
+        let indent = match format {
+            ConfigFormat::Toml => {
+                out.push_str(&format!("[{}]\n", section.name));
+                ""
+            }
+            _ => {
+                out.push_str(&format!("{}:\n", section.name));
+                "  "
+            }
+        };
+
+        for (index, (key, doc, sample)) in section.fields.iter().enumerate() {
+            if index > 0 {
+                out.push('\n');
+            }
+            push_comment(&mut out, indent, doc);
+            let (value, prefix) = match sample {
+                Sample::Commented(json) => (serde_json::from_str(json).unwrap_or_default(), "# "),
+                _ => (defaults[section.name][key].clone(), ""),
+            };
+            out.push_str(&format!("{}{}{}\n", indent, prefix, render_field(format, key, &value)));
+        }
+    }
+    out
+}
+
+fn render_field(format: ConfigFormat, key: &str, value: &Value) -> String {
+    let value = without_nulls(value.clone());
+    match format {
+        ConfigFormat::Toml => {
+            let value = toml::Value::try_from(value)
+                .map(|value| value.to_string())
+                .unwrap_or_default();
+            format!("{} = {}", key, value)
+        }
+        // YAML accepts JSON values as they are
+        _ => format!("{}: {}", key, value),
+    }
+}
+
+/// Unset optional fields inside values are left out; TOML has no null anyway
+fn without_nulls(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| (key, without_nulls(value)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.into_iter().map(without_nulls).collect()),
+        value => value,
+    }
+}
+
+fn push_comment(out: &mut String, indent: &str, text: &str) {
+    for line in text.lines() {
+        out.push_str(&format!("{}# {}\n", indent, line));
+    }
+}
@@ -0,0 +1,86 @@
+//! Checks of settings that deserialize fine but can't work.
+
+use std::collections::HashSet;
+
+use crate::localization::Lang;
+use crate::milestones::config::MilestoneKind;
+use crate::scheduler::cron::Schedule;
+
+use super::Config;
+
+/// Returns a description of every problem found, empty if there are none
+pub fn check(config: &Config) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if Lang::from_code(&config.bot.default_language).is_none() {
+        problems.push(format!(
+            "bot.default_language: unsupported language '{}'",
+            config.bot.default_language
+        ));
+    }
+    if config.bot.utc_offset().is_none() {
+        problems.push(format!(
+            "bot.utc_offset_minutes: {} is not a valid UTC offset",
+            config.bot.utc_offset_minutes
+        ));
+    }
+    for (index, rule) in config.bot.milestones.iter().enumerate() {
+        if rule.kind != MilestoneKind::FirstVote && rule.count == 0 {
+            problems.push(format!("bot.milestones[{}].count: must be greater than 0", index));
+        }
+    }
+
+    if config.bot.token.is_some() && config.bot.token_file.is_some() {
+        problems.push("bot: set either token or token_file, not both".to_string());
+    }
+
+    let uri = config.database.connection_uri.expose();
+    if !uri.starts_with("mongodb://") && !uri.starts_with("mongodb+srv://") {
+        problems.push("database.connection_uri: must start with mongodb:// or mongodb+srv://".to_string());
+    }
+
+    let security = &config.security;
+    if security.request_limit == 0 {
+        problems.push("security.request_limit: must be greater than 0".to_string());
+    }
+    if security.time_window_seconds == 0 {
+        problems.push("security.time_window_seconds: must be greater than 0".to_string());
+    }
+    let whitelist: HashSet<i64> = security.whitelist.iter().copied().collect();
+    let mut both: Vec<i64> = security
+        .blacklist
+        .iter()
+        .copied()
+        .filter(|id| whitelist.contains(id))
+        .collect();
+    both.sort_unstable();
+    both.dedup();
+    if !both.is_empty() {
+        let ids: Vec<String> = both.iter().map(i64::to_string).collect();
+        problems.push(format!(
+            "security: user id(s) {} are in both whitelist and blacklist",
+            ids.join(", ")
+        ));
+    }
+
+    let admin = &config.admin;
+    if admin.tcp_port != 0 && admin.tcp_token.as_ref().is_none_or(|token| token.expose().is_empty()) {
+        problems.push("admin.tcp_token: required when admin.tcp_port is set".to_string());
+    }
+
+    let scheduler = &config.scheduler;
+    let jobs = [
+        ("morning_post", &scheduler.morning_post),
+        ("reminder", &scheduler.reminder),
+        ("daily_summary", &scheduler.daily_summary),
+        ("weekly_digest", &scheduler.weekly_digest),
+        ("monthly_digest", &scheduler.monthly_digest),
+    ];
+    for (name, expression) in jobs {
+        if let Some(Err(e)) = expression.as_deref().map(Schedule::parse) {
+            problems.push(format!("scheduler.{}: {}", name, e));
+        }
+    }
+
+    problems
+}
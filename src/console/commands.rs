@@ -0,0 +1,346 @@
+//! Console commands. Each returns its output as text, so the loop decides where it goes.
+
+use chrono::Utc;
+use mongodb::bson::DateTime;
+use teloxide::prelude::*;
+
+use crate::database_actions::{day::Day, DatabaseService, StoreError, VoteReceipt};
+use crate::handlers::common::today_stats_message;
+use crate::handlers::voting::{record_vote, Voter};
+use crate::history::{
+    export::{load_records, serialize_records},
+    import::{apply_import, parse_records, plan_import, render_invalid, render_plan},
+    parse_date, HistoryFormat,
+};
+use crate::localization::Lang;
+
+use super::ConsoleContext;
+
+const HELP: &str = "Available commands:
+  today - Show today's tally and who voted
+  history <YYYY-MM-DD> - Show the tally of a past day
+  vote <user id> <late|ontime> [YYYY-MM-DD] - Cast a vote on behalf of a user, today by default
+  unvote <user id> [YYYY-MM-DD] - Remove a user's vote, today by default
+  reset <today|YYYY-MM-DD> [confirm] - Show what resetting a day clears, or clear it with 'confirm'
+  ban <user id> - Ignore every request of a user until unbanned or restarted
+  unban <user id> - Lift a ban made with 'ban'
+  limits - Show the rate limits, bans and users close to the limit
+  say <text> - Send a message to notification_chat_id
+  export <csv|json> <path> [from] [to] - Write the history to a file, dates as YYYY-MM-DD
+  import <path> [commit] - Preview importing a CSV/JSON history file, or save it with 'commit'
+  reload - Reload the config file and apply the bot and security settings
+  test - Test command that responds with 'test'
+  help - Show this help message
+  exit - Stop the bot; Ctrl-C and Ctrl-D do the same";
+
+/// Runs one console command line and returns what it printed
+pub async fn execute(context: &ConsoleContext, line: &str) -> String {
+    let mut words = line.split_whitespace();
+    let command = words.next().unwrap_or_default();
+    let arguments: Vec<&str> = words.collect();
+
+    match command {
+        "test" => "test".to_string(),
+        "today" => today_command(context).await,
+        "history" => history_command(context, &arguments).await,
+        "vote" => vote_command(context, &arguments).await,
+        "unvote" => unvote_command(context, &arguments).await,
+        "reset" => reset_command(context, &arguments).await,
+        "ban" => ban_command(context, &arguments).await,
+        "unban" => unban_command(context, &arguments).await,
+        "limits" => limits_command(context).await,
+        // The message is the rest of the line as typed, spacing included
+        "say" => say_command(context, line[command.len()..].trim()).await,
+        "export" => export_command(&context.database_service, &arguments).await,
+        "import" => import_command(&context.database_service, &arguments).await,
+        "reload" => match context.reloader.reload_and_log("console").await {
+            Ok(changed) if changed.is_empty() => "Configuration reloaded, nothing changed".to_string(),
+            Ok(changed) => format!("Configuration reloaded, changed: {}", changed.join(", ")),
+            Err(e) => format!("Configuration rejected, keeping the running one:\n{}", e),
+        },
+        "help" => HELP.to_string(),
+        _ => format!("Unknown command: {}", line),
+    }
+}
+
+async fn today_command(context: &ConsoleContext) -> String {
+    match context.database_service.check_today_document().await {
+        Ok(day) => describe_day(&context.database_service, &day).await,
+        Err(e) => format!("Failed to load today: {}", e),
+    }
+}
+
+async fn history_command(context: &ConsoleContext, arguments: &[&str]) -> String {
+    let Some(date) = arguments.first().and_then(|date| parse_date(date)).filter(|_| arguments.len() == 1) else {
+        return "Usage: history <YYYY-MM-DD>".to_string();
+    };
+    match context.database_service.get_day_stats(Day::start_of(date)).await {
+        Ok(day) => format!("{}:\n{}", date, describe_day(&context.database_service, &day).await),
+        Err(StoreError::NotFound(_)) => format!("Nobody voted on {}", date),
+        Err(e) => format!("Failed to load {}: {}", date, e),
+    }
+}
+
+async fn vote_command(context: &ConsoleContext, arguments: &[&str]) -> String {
+    let usage = "Usage: vote <user id> <late|ontime> [YYYY-MM-DD]";
+    let (user_id, side, date) = match arguments {
+        [user_id, side, date @ ..] if date.len() <= 1 => (user_id.parse::<i64>(), *side, date.first()),
+        _ => return usage.to_string(),
+    };
+    let is_late = match side {
+        "late" => true,
+        "ontime" => false,
+        _ => return usage.to_string(),
+    };
+    let (Ok(user_id), Some(date)) = (user_id, day_argument(date.copied())) else {
+        return usage.to_string();
+    };
+
+    let bot_config = context.bot_config.load_full();
+    let voter = Voter::Id(user_id);
+    let receipt = match record_vote(&context.bot, &context.database_service, &bot_config, voter, date, is_late).await {
+        Ok(receipt) => receipt,
+        Err(e) => return format!("Failed to record the vote: {}", e),
+    };
+    format!("Vote of {} recorded as {}{}", user_id, side, queued_note(receipt))
+}
+
+async fn unvote_command(context: &ConsoleContext, arguments: &[&str]) -> String {
+    let usage = "Usage: unvote <user id> [YYYY-MM-DD]";
+    let (user_id, date) = match arguments {
+        [user_id, date @ ..] if date.len() <= 1 => (user_id.parse::<i64>(), date.first()),
+        _ => return usage.to_string(),
+    };
+    let (Ok(user_id), Some(date)) = (user_id, day_argument(date.copied())) else {
+        return usage.to_string();
+    };
+
+    match context.database_service.remove_vote(date, user_id).await {
+        Ok(receipt) => format!("Vote of {} removed{}", user_id, queued_note(receipt)),
+        Err(e) => format!("Failed to remove the vote: {}", e),
+    }
+}
+
+async fn reset_command(context: &ConsoleContext, arguments: &[&str]) -> String {
+    let usage = "Usage: reset <today|YYYY-MM-DD> [confirm]";
+    let (date, confirm) = match arguments {
+        [date] => (*date, false),
+        [date, "confirm"] => (*date, true),
+        _ => return usage.to_string(),
+    };
+    let Some(date) = day_argument(Some(date)) else {
+        return usage.to_string();
+    };
+
+    let database_service = &context.database_service;
+    let day = match database_service.get_day_stats(date).await {
+        Ok(day) => day,
+        Err(StoreError::NotFound(_)) => return "Nobody voted on that day, nothing to reset".to_string(),
+        Err(e) => return format!("Failed to load the day: {}", e),
+    };
+    let label = day.naive_date();
+    if !confirm {
+        return format!(
+            "Resetting {} clears {} vote(s) and {} milestone announcement(s).\nRun 'reset {} confirm' to do it.",
+            label,
+            day.votes_yes.len() + day.votes_no.len(),
+            day.announced_milestones.len(),
+            label
+        );
+    }
+    match database_service.reset_day(date).await {
+        Ok(_) => {
+            log::warn!("Day {} reset from the console", label);
+            format!("Day {} reset", label)
+        }
+        Err(e) => format!("Failed to reset {}: {}", label, e),
+    }
+}
+
+async fn ban_command(context: &ConsoleContext, arguments: &[&str]) -> String {
+    let Some(user_id) = user_argument(arguments) else {
+        return "Usage: ban <user id>".to_string();
+    };
+    if context.security_manager.ban(user_id).await {
+        log::warn!("User {} banned from the console", user_id);
+        format!("User {} banned until unbanned or restarted", user_id)
+    } else {
+        format!("User {} is already banned", user_id)
+    }
+}
+
+async fn unban_command(context: &ConsoleContext, arguments: &[&str]) -> String {
+    let Some(user_id) = user_argument(arguments) else {
+        return "Usage: unban <user id>".to_string();
+    };
+    let unbanned = context.security_manager.unban(user_id).await;
+    if unbanned {
+        log::info!("User {} unbanned from the console", user_id);
+    }
+    let blacklisted = context.security_manager.config().blacklist.contains(&user_id);
+    match (unbanned, blacklisted) {
+        (_, true) => format!(
+            "User {} is in security.blacklist and stays blocked until removed from the config",
+            user_id
+        ),
+        (true, false) => format!("User {} unbanned", user_id),
+        (false, false) => format!("User {} is not banned", user_id),
+    }
+}
+
+async fn limits_command(context: &ConsoleContext) -> String {
+    let security_manager = &context.security_manager;
+    let config = security_manager.config();
+
+    let mut lines = vec![format!(
+        "Rate limit: {} requests per {} seconds, protection {}",
+        config.request_limit,
+        config.time_window_seconds,
+        if config.ddos_protection_enabled { "enabled" } else { "disabled" }
+    )];
+    lines.push(format!("Whitelist: {}", join_ids(&config.whitelist)));
+    lines.push(format!("Blacklist: {}", join_ids(&config.blacklist)));
+    lines.push(format!("Banned from the console: {}", join_ids(&security_manager.banned().await)));
+
+    let rates = security_manager.user_rates().await;
+    if rates.is_empty() {
+        lines.push("No requests in the current window".to_string());
+    }
+    for rate in rates {
+        let state = match rate.blocked_for {
+            Some(wait) => format!(", blocked for {}s", wait.as_secs()),
+            None => String::new(),
+        };
+        lines.push(format!(
+            "  {}: {}/{} requests{}",
+            rate.user_id, rate.requests, config.request_limit, state
+        ));
+    }
+    lines.join("\n")
+}
+
+async fn say_command(context: &ConsoleContext, text: &str) -> String {
+    if text.is_empty() {
+        return "Usage: say <text>".to_string();
+    }
+    let chat_id = context.bot_config.load().notification_chat_id;
+    if chat_id == 0 {
+        return "notification_chat_id is not set".to_string();
+    }
+    match context.bot.send_message(ChatId(chat_id), text).await {
+        Ok(_) => format!("Sent to chat {}", chat_id),
+        Err(e) => format!("Failed to send the message: {}", e),
+    }
+}
+
+async fn export_command(database_service: &DatabaseService, arguments: &[&str]) -> String {
+    let usage = "Usage: export <csv|json> <path> [from YYYY-MM-DD] [to YYYY-MM-DD]";
+    let (format, path, dates) = match arguments {
+        [format, path, dates @ ..] if dates.len() <= 2 => match HistoryFormat::parse(format) {
+            Some(format) => (format, *path, dates),
+            None => return usage.to_string(),
+        },
+        _ => return usage.to_string(),
+    };
+    let Some(dates) = dates.iter().map(|date| parse_date(date)).collect::<Option<Vec<_>>>() else {
+        return usage.to_string();
+    };
+
+    let records = match load_records(database_service, dates.first().copied(), dates.get(1).copied()).await {
+        Ok(records) => records,
+        Err(e) => return format!("Failed to load history: {}", e),
+    };
+    let result = serialize_records(&records, format)
+        .and_then(|bytes| std::fs::write(path, bytes).map_err(Into::into));
+    match result {
+        Ok(()) => format!("Exported {} days to {}", records.len(), path),
+        Err(e) => format!("Failed to export history: {}", e),
+    }
+}
+
+async fn import_command(database_service: &DatabaseService, arguments: &[&str]) -> String {
+    let (path, commit) = match arguments {
+        [path] => (*path, false),
+        [path, "commit"] => (*path, true),
+        _ => return "Usage: import <path> [commit]".to_string(),
+    };
+    let contents = match std::fs::read(path) {
+        Ok(contents) => contents,
+        Err(e) => return format!("Failed to read {}: {}", path, e),
+    };
+
+    let format = HistoryFormat::detect(Some(path), &contents);
+    let records = match parse_records(&contents, format, Utc::now().date_naive()) {
+        Ok(records) => records,
+        Err(errors) => return render_invalid(Lang::En, &errors),
+    };
+
+    let imported = match plan_import(database_service, &records).await {
+        Ok(plan) if commit => apply_import(database_service, &plan).await.map(|_| plan),
+        planned => planned,
+    };
+    match imported {
+        Ok(plan) => {
+            let mut output = render_plan(Lang::En, &plan, commit);
+            if !commit && !plan.changes.is_empty() {
+                output.push_str(&format!("\n\nRun 'import {} commit' to save these changes.", path));
+            }
+            output
+        }
+        Err(e) => format!("Failed to import history: {}", e),
+    }
+}
+
+/// The tally of `day` followed by who voted which way
+async fn describe_day(database_service: &DatabaseService, day: &Day) -> String {
+    let voters: Vec<i64> = day.votes_yes.iter().chain(&day.votes_no).copied().collect();
+    // Names are a convenience; without them the ids are still shown
+    let profiles = database_service.get_user_profiles(&voters).await.unwrap_or_default();
+    let name = |user_id: &i64| match profiles.iter().find(|profile| profile.user_id == *user_id) {
+        Some(profile) => match &profile.username {
+            Some(username) => format!("{} ({}, @{})", user_id, profile.display_name(), username),
+            None => format!("{} ({})", user_id, profile.display_name()),
+        },
+        None => user_id.to_string(),
+    };
+    let names = |ids: &[i64]| match ids {
+        [] => "-".to_string(),
+        ids => ids.iter().map(name).collect::<Vec<_>>().join(", "),
+    };
+
+    format!(
+        "{}\n\nLate: {}\nOn time: {}",
+        today_stats_message(Lang::En, day, None),
+        names(&day.votes_yes),
+        names(&day.votes_no)
+    )
+}
+
+/// Start of the day named by `argument`: `today` or missing means today, else YYYY-MM-DD
+fn day_argument(argument: Option<&str>) -> Option<DateTime> {
+    match argument {
+        None | Some("today") => Some(Day::today_start()),
+        Some(date) => parse_date(date).map(Day::start_of),
+    }
+}
+
+fn user_argument(arguments: &[&str]) -> Option<i64> {
+    match arguments {
+        [user_id] => user_id.parse().ok(),
+        _ => None,
+    }
+}
+
+fn queued_note(receipt: VoteReceipt) -> &'static str {
+    match receipt {
+        VoteReceipt::Recorded => "",
+        VoteReceipt::Queued => " (queued, the database is unreachable)",
+    }
+}
+
+fn join_ids(ids: &[i64]) -> String {
+    match ids {
+        [] => "-".to_string(),
+        ids => ids.iter().map(i64::to_string).collect::<Vec<_>>().join(", "),
+    }
+}
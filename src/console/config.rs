@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::secret::Secret;
+
+/// Where the console commands are served for `latebot admin`
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AdminConfig {
+    /// Unix socket serving the console commands; empty disables it
+    #[serde(default = "default_socket_path")]
+    pub socket_path: String,
+    /// Port on 127.0.0.1 serving the same commands; 0 disables it
+    #[serde(default)]
+    pub tcp_port: u16,
+    /// Shared secret a client of `tcp_port` must send before its command; the port needs one
+    #[serde(default)]
+    pub tcp_token: Option<Secret>,
+}
+
+fn default_socket_path() -> String {
+    "latebot-admin.sock".to_string()
+}
+
+impl Default for AdminConfig {
+    fn default() -> Self {
+        Self {
+            socket_path: default_socket_path(),
+            tcp_port: 0,
+            tcp_token: None,
+        }
+    }
+}
@@ -1,46 +1,109 @@
-use std::io::{self, BufRead, Write};
+//! Operator console on the terminal the bot runs in.
+//!
+//! Lines are read with line editing and history on a thread of their own, so waiting for
+//! input never occupies a runtime worker. Without a terminal (e.g. under Docker without
+//! `-t`) the console is disabled and [`remote`] is the way in. `exit`, Ctrl-C and Ctrl-D
+//! shut the bot down gracefully.
 
-/// Starts the console interface in a separate async task
-pub async fn start_console_interface() {
-    tokio::spawn(async move {
-        console_interface_loop().await;
-    });
+use std::io::IsTerminal;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rustyline::{config::Configurer, error::ReadlineError, DefaultEditor};
+use teloxide::{dispatching::ShutdownToken, Bot};
+use tokio::runtime::Handle;
+
+use crate::config::reload::ConfigReloader;
+use crate::config::SharedBotConfig;
+use crate::database_actions::DatabaseService;
+use crate::securiy::manager::SecurityManager;
+
+pub mod commands;
+pub mod config;
+pub mod remote;
+
+/// Commands remembered for the up arrow
+const HISTORY_SIZE: usize = 500;
+
+/// What console commands act on; the same instances the dispatcher uses, so a vote cast or
+/// a ban made here is seen by the bot right away. Shared with the admin server
+pub struct ConsoleContext {
+    pub bot: Bot,
+    pub database_service: DatabaseService,
+    pub security_manager: Arc<SecurityManager>,
+    pub bot_config: SharedBotConfig,
+    pub reloader: Arc<ConfigReloader>,
+}
+
+/// Starts the console interface on its own thread, unless stdin isn't a terminal.
+///
+/// `shutdown` stops the dispatcher when the operator exits the console. If the terminal
+/// can't be used, only the console ends and the bot keeps running.
+pub fn start_console_interface(context: Arc<ConsoleContext>, shutdown: ShutdownToken) {
+    if !std::io::stdin().is_terminal() {
+        log::info!("Console disabled: stdin is not a terminal");
+        return;
+    }
+
+    let runtime = Handle::current();
+    let spawned = std::thread::Builder::new()
+        .name("console".to_string())
+        .spawn(move || {
+            if console_interface_loop(&context, &runtime) {
+                runtime.spawn(request_shutdown(shutdown));
+            }
+        });
+    if let Err(e) = spawned {
+        log::error!("Failed to start the console: {}", e);
+    }
 }
 
-/// Main loop for the console interface
-async fn console_interface_loop() {
+/// Main loop for the console interface; returns `true` when the operator leaves it, and
+/// `false` when the terminal fails
+fn console_interface_loop(context: &ConsoleContext, runtime: &Handle) -> bool {
+    let mut editor = match DefaultEditor::new() {
+        Ok(editor) => editor,
+        Err(e) => {
+            log::error!("Console unavailable, the bot keeps running without it: {}", e);
+            return false;
+        }
+    };
+    if let Err(e) = editor.set_max_history_size(HISTORY_SIZE) {
+        log::warn!("Console history is unavailable: {}", e);
+    }
     println!("Console interface started. Type 'help' for available commands.");
-    
-    let stdin = io::stdin();
-    let mut handle = stdin.lock();
-    let mut input = String::new();
-    
+
     loop {
-        input.clear();
-        print!("latebot> ");
-        io::stdout().flush().unwrap_or_default();
-        
-        if handle.read_line(&mut input).unwrap_or(0) == 0 {
-            // EOF or error
-            break;
-        }
-        
-        let command = input.trim();
-        
-        match command {
-            "test" => println!("test"),
-            "help" => {
-                println!("Available commands:");
-                println!("  test - Test command that responds with 'test'");
-                println!("  help - Show this help message");
-                println!("  exit - Exit the console interface");
-            },
-            "exit" => {
-                println!("Exiting console interface...");
-                break;
-            },
+        let input = match editor.readline("latebot> ") {
+            Ok(input) => input,
+            // The terminal is in raw mode while reading, so Ctrl-C arrives here instead of
+            // as a signal the dispatcher would handle
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => break,
+            Err(e) => {
+                log::error!("Failed to read console input, the bot keeps running without the console: {}", e);
+                return false;
+            }
+        };
+
+        match input.trim() {
+            "exit" => break,
             "" => {}, // Ignore empty commands
-            _ => println!("Unknown command: {}", command),
+            command => {
+                let _ = editor.add_history_entry(command);
+                println!("{}", runtime.block_on(commands::execute(context, command)));
+            }
+        }
+    }
+    println!("Shutting down...");
+    true
+}
+
+/// Stops the dispatcher, waiting for it to start if it hasn't yet
+async fn request_shutdown(shutdown: ShutdownToken) {
+    loop {
+        match shutdown.shutdown() {
+            Ok(stopped) => return stopped.await,
+            Err(_) => tokio::time::sleep(Duration::from_millis(100)).await,
         }
     }
 }
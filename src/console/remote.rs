@@ -0,0 +1,187 @@
+//! The console commands served on a Unix socket and, optionally, a localhost TCP port, for
+//! `latebot admin`.
+//!
+//! A connection carries a single command: the client sends one line and the server answers
+//! with the command's output, then closes the connection. The socket is created accessible
+//! to its owner only, which is its access control. The port only listens on 127.0.0.1, but
+//! any local user can reach it, so clients must first send `admin.tcp_token` on a line of
+//! its own.
+
+use std::sync::Arc;
+
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+use super::{commands, config::AdminConfig, ConsoleContext};
+use crate::config::secret::Secret;
+
+/// Longest command line accepted, so a stray client can't make the bot buffer without end
+const MAX_LINE: u64 = 64 * 1024;
+
+/// Starts listening on the socket and port set in `config`
+pub async fn start_admin_server(context: Arc<ConsoleContext>, config: &AdminConfig) {
+    #[cfg(unix)]
+    if !config.socket_path.is_empty() {
+        match bind_socket(&config.socket_path) {
+            Ok(listener) => {
+                log::info!("Admin commands available on socket {}", config.socket_path);
+                let context = context.clone();
+                tokio::spawn(async move {
+                    loop {
+                        match listener.accept().await {
+                            Ok((stream, _)) => serve(context.clone(), stream, "socket".to_string(), None),
+                            Err(e) => log::warn!("Failed to accept an admin connection: {}", e),
+                        }
+                    }
+                });
+            }
+            Err(e) => log::error!("Admin socket {} is unavailable: {}", config.socket_path, e),
+        }
+    }
+
+    if config.tcp_port != 0 {
+        // The config check refuses a port without a token; this only guards against a bypass
+        let Some(token) = config.tcp_token.clone().filter(|token| !token.expose().is_empty()) else {
+            return log::error!("Admin port {} is disabled: admin.tcp_token is not set", config.tcp_port);
+        };
+        let token = Arc::new(token);
+        match TcpListener::bind(("127.0.0.1", config.tcp_port)).await {
+            Ok(listener) => {
+                log::info!("Admin commands available on 127.0.0.1:{}", config.tcp_port);
+                tokio::spawn(async move {
+                    loop {
+                        match listener.accept().await {
+                            Ok((stream, peer)) => serve(context.clone(), stream, peer.to_string(), Some(token.clone())),
+                            Err(e) => log::warn!("Failed to accept an admin connection: {}", e),
+                        }
+                    }
+                });
+            }
+            Err(e) => log::error!("Admin port {} is unavailable: {}", config.tcp_port, e),
+        }
+    }
+}
+
+/// Binds the socket, replacing one left behind by a previous run, accessible to the owner only.
+///
+/// The socket is bound inside a fresh directory only the owner can enter and moved into
+/// place once its mode is restricted, so it is never reachable with the default mode.
+#[cfg(unix)]
+fn bind_socket(path: &str) -> std::io::Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+    // A socket nobody answers on is stale; one that answers belongs to another instance
+    if std::os::unix::net::UnixStream::connect(path).is_ok() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AddrInUse,
+            "another instance is listening on it",
+        ));
+    }
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+
+    let mut private_dir = std::path::PathBuf::from(path).into_os_string();
+    private_dir.push(format!(".{}.tmp", std::process::id()));
+    let private_dir = std::path::PathBuf::from(private_dir);
+    std::fs::DirBuilder::new().mode(0o700).create(&private_dir)?;
+    let private_path = private_dir.join("admin.sock");
+    let bound = tokio::net::UnixListener::bind(&private_path).and_then(|listener| {
+        std::fs::set_permissions(&private_path, std::fs::Permissions::from_mode(0o600))?;
+        std::fs::rename(&private_path, path)?;
+        Ok(listener)
+    });
+    let _ = std::fs::remove_file(&private_path);
+    std::fs::remove_dir(&private_dir)?;
+    bound
+}
+
+/// Answers the single command sent over `stream`, after checking `token` when one is required
+fn serve<S>(context: Arc<ConsoleContext>, stream: S, peer: String, token: Option<Arc<Secret>>)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut reader = BufReader::new(reader.take(MAX_LINE));
+        let mut line = String::new();
+        if let Err(e) = reader.read_line(&mut line).await {
+            return log::warn!("Failed to read an admin command from {}: {}", peer, e);
+        }
+        let authorized = match token {
+            Some(token) => {
+                let authorized = tokens_match(line.trim_end_matches(['\r', '\n']), token.expose());
+                line.clear();
+                if let Err(e) = reader.read_line(&mut line).await {
+                    return log::warn!("Failed to read an admin command from {}: {}", peer, e);
+                }
+                authorized
+            }
+            None => true,
+        };
+
+        let output = match line.trim() {
+            _ if !authorized => {
+                log::warn!("Refused an admin command from {}: wrong admin.tcp_token", peer);
+                "Wrong admin token; set admin.tcp_token to the bot's".to_string()
+            }
+            "" => return,
+            "exit" => "'exit' only stops the bot from its own console; stop the container instead".to_string(),
+            command => {
+                log::info!("Admin command from {}: {}", peer, command);
+                commands::execute(&context, command).await
+            }
+        };
+        let written = async {
+            writer.write_all(output.as_bytes()).await?;
+            writer.write_all(b"\n").await?;
+            writer.shutdown().await
+        };
+        if let Err(e) = written.await {
+            log::warn!("Failed to answer an admin command from {}: {}", peer, e);
+        }
+    });
+}
+
+/// Compares without stopping at the first difference, so timing doesn't reveal the token
+fn tokens_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len() && given.bytes().zip(expected.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_match_only_when_equal() {
+        assert!(tokens_match("s3cret-token", "s3cret-token"));
+        assert!(!tokens_match("s3cret-tokem", "s3cret-token"));
+        assert!(!tokens_match("s3cret", "s3cret-token"));
+        assert!(!tokens_match("", "s3cret-token"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn socket_is_private_and_replaces_a_stale_one() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("latebot-admin-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("admin.sock");
+        let path = path.to_str().unwrap();
+
+        drop(std::os::unix::net::UnixListener::bind(path).unwrap());
+        let listener = bind_socket(path).unwrap();
+        let mode = std::fs::metadata(path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        // Only the socket is left, not the directory it was bound in
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        // A live socket belongs to another instance
+        assert_eq!(bind_socket(path).unwrap_err().kind(), std::io::ErrorKind::AddrInUse);
+        drop(listener);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
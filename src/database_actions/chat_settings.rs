@@ -0,0 +1,9 @@
+use serde::{Deserialize, Serialize};
+
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChatSettings {
+    pub chat_id: i64,
+    /// Language code chosen for the chat with `/language`; `None` means per-user detection
+    pub language: Option<String>,
+}
@@ -1,10 +1,86 @@
+use chrono::{NaiveDate, NaiveTime};
 use mongodb::bson::DateTime;
 use serde::{Deserialize, Serialize};
 
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Day {
     pub date: DateTime,
+    #[serde(default)]
     pub votes_yes: Vec<i64>,
+    #[serde(default)]
     pub votes_no: Vec<i64>,
+    /// Layout version the document was written with, see [`super::migrations`]
+    #[serde(default)]
+    pub schema_version: u32,
+    /// Incremented on every write, so readers can tell whether their copy is current
+    #[serde(default)]
+    pub version: i64,
+    /// Keys of milestone announcements already posted for this day
+    #[serde(default)]
+    pub announced_milestones: Vec<String>,
+    /// Native Telegram polls posted for this day
+    #[serde(default)]
+    pub polls: Vec<PollRef>,
+    /// Every vote change in the order it happened, used to chart the day
+    #[serde(default)]
+    pub vote_log: Vec<VoteEvent>,
+}
+
+/// A single vote change; `vote` is `None` when the user withdrew their vote
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VoteEvent {
+    pub user_id: i64,
+    pub vote: Option<bool>,
+    pub at: DateTime,
+}
+
+/// A native poll message whose answers count as votes for the day it was posted on
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PollRef {
+    pub poll_id: String,
+    pub chat_id: i64,
+    pub message_id: i32,
+}
+
+impl Day {
+    /// Empty day starting at `date`, in the current layout
+    pub fn new(date: DateTime) -> Self {
+        Self {
+            date,
+            votes_yes: Vec::new(),
+            votes_no: Vec::new(),
+            schema_version: super::migrations::SCHEMA_VERSION,
+            version: 0,
+            announced_milestones: Vec::new(),
+            polls: Vec::new(),
+            vote_log: Vec::new(),
+        }
+    }
+
+    /// Start of the (UTC) day containing `at`, which is the key days are stored under
+    pub fn day_start(at: DateTime) -> DateTime {
+        DateTime::from_millis(at.timestamp_millis() - at.timestamp_millis().rem_euclid(86400000))
+    }
+
+    pub fn today_start() -> DateTime {
+        Self::day_start(DateTime::now())
+    }
+
+    /// Key of the given calendar day
+    pub fn start_of(date: NaiveDate) -> DateTime {
+        DateTime::from_millis(date.and_time(NaiveTime::MIN).and_utc().timestamp_millis())
+    }
+
+    /// Calendar day this document is for
+    pub fn naive_date(&self) -> NaiveDate {
+        chrono::DateTime::from_timestamp_millis(self.date.timestamp_millis())
+            .unwrap_or_default()
+            .date_naive()
+    }
+
+    /// Whether the day's verdict is "late", i.e. "late" votes outnumber "on time" ones
+    pub fn is_late(&self) -> bool {
+        self.votes_yes.len() > self.votes_no.len()
+    }
 }
@@ -0,0 +1,87 @@
+use std::fmt;
+
+use mongodb::error::{ErrorKind, RETRYABLE_WRITE_ERROR, TRANSIENT_TRANSACTION_ERROR};
+
+use super::indexes::is_duplicate_key;
+
+/// Error returned by every [`super::DatabaseService`] method
+#[derive(Debug)]
+pub enum StoreError {
+    /// The database couldn't be reached; the operation may succeed once it is back
+    Connection(mongodb::error::Error),
+    /// The server refused the operation, e.g. a failed validation or a bad update; retrying
+    /// it won't help, so it is never journaled
+    Rejected(mongodb::error::Error),
+    /// The requested document doesn't exist
+    NotFound(String),
+    /// The write collided with another one, e.g. on a unique index
+    Conflict(mongodb::error::Error),
+    /// A document couldn't be converted from or to its stored form
+    Serialization(String),
+}
+
+impl StoreError {
+    /// Short machine-friendly name of the error kind, used in logs and health reports
+    pub fn kind(&self) -> &'static str {
+        match self {
+            StoreError::Connection(_) => "connection",
+            StoreError::Rejected(_) => "rejected",
+            StoreError::NotFound(_) => "not_found",
+            StoreError::Conflict(_) => "conflict",
+            StoreError::Serialization(_) => "serialization",
+        }
+    }
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Connection(e) => write!(f, "database unavailable: {}", e),
+            StoreError::Rejected(e) => write!(f, "rejected by the database: {}", e),
+            StoreError::NotFound(what) => write!(f, "{} not found", what),
+            StoreError::Conflict(e) => write!(f, "conflicting write: {}", e),
+            StoreError::Serialization(e) => write!(f, "malformed document: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StoreError::Connection(e) | StoreError::Rejected(e) | StoreError::Conflict(e) => Some(e),
+            StoreError::NotFound(_) | StoreError::Serialization(_) => None,
+        }
+    }
+}
+
+impl From<mongodb::error::Error> for StoreError {
+    fn from(error: mongodb::error::Error) -> Self {
+        if is_duplicate_key(&error) {
+            return StoreError::Conflict(error);
+        }
+        match error.kind.as_ref() {
+            ErrorKind::BsonDeserialization(e) => StoreError::Serialization(e.to_string()),
+            ErrorKind::BsonSerialization(e) => StoreError::Serialization(e.to_string()),
+            ErrorKind::Io(_) | ErrorKind::ServerSelection { .. } | ErrorKind::ConnectionPoolCleared { .. } => {
+                StoreError::Connection(error)
+            }
+            // Labels the driver and server put on network failures and failovers
+            _ if error.contains_label(RETRYABLE_WRITE_ERROR) || error.contains_label(TRANSIENT_TRANSACTION_ERROR) => {
+                StoreError::Connection(error)
+            }
+            _ => StoreError::Rejected(error),
+        }
+    }
+}
+
+impl From<mongodb::bson::de::Error> for StoreError {
+    fn from(error: mongodb::bson::de::Error) -> Self {
+        StoreError::Serialization(error.to_string())
+    }
+}
+
+impl From<mongodb::bson::ser::Error> for StoreError {
+    fn from(error: mongodb::bson::ser::Error) -> Self {
+        StoreError::Serialization(error.to_string())
+    }
+}
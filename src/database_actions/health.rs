@@ -0,0 +1,123 @@
+//! Tracking whether the database is reachable.
+//!
+//! Startup waits for the database with exponential backoff; afterwards a background task
+//! pings it periodically. While it is down handlers answer with a "database unavailable"
+//! notice instead of waiting for every operation to time out, and votes are journaled. Once
+//! it is back the bot carries on by itself, running migrations first if startup never got
+//! to them and then replaying the journal.
+
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use mongodb::bson::doc;
+use tokio::time::{sleep, Instant};
+
+use crate::config::DatabaseConfig;
+
+use super::{DatabaseService, DatabaseServiceInner, StoreError};
+
+/// First delay of the startup backoff
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+impl DatabaseServiceInner {
+    /// Result of the last ping
+    pub fn is_available(&self) -> bool {
+        self.available.load(Ordering::Relaxed)
+    }
+
+    /// Checks the connection with a round trip to the server
+    pub async fn ping(&self) -> Result<(), StoreError> {
+        self.db.run_command(doc! { "ping": 1 }, None).await?;
+        Ok(())
+    }
+
+    /// Pings the database and records the outcome, logging changes of state
+    pub async fn check_health(&self) -> bool {
+        let result = self.ping().await;
+        let available = result.is_ok();
+        let was_available = self.available.swap(available, Ordering::Relaxed);
+
+        match result {
+            Ok(()) if !was_available => log::info!("Database is available"),
+            Err(e) if was_available => log::error!("Database became unavailable: {}", e),
+            _ => {}
+        }
+        if available {
+            self.prepare().await;
+            match self.replay_journal().await {
+                Ok(0) => {}
+                Ok(applied) => log::info!("Replayed {} journaled vote(s)", applied),
+                Err(e) => log::error!("Failed to replay journaled votes: {}", e),
+            }
+        }
+        available
+    }
+
+    /// Marks the database as down until the next successful health check
+    pub fn mark_unavailable(&self) {
+        if self.available.swap(false, Ordering::Relaxed) {
+            log::error!("Database became unavailable");
+        }
+    }
+
+    /// Waits for the database with exponential backoff, at most `startup_timeout_seconds`.
+    ///
+    /// Returns `false` if the database is still down, in which case the bot starts degraded.
+    pub async fn wait_until_available(&self, config: &DatabaseConfig) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(config.startup_timeout_seconds);
+        let max_delay = Duration::from_secs(config.retry_max_seconds.max(1));
+        let mut delay = INITIAL_RETRY_DELAY.min(max_delay);
+
+        loop {
+            match self.ping().await {
+                Ok(()) => return self.check_health().await,
+                Err(e) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        log::error!(
+                            "Database is still unreachable after {}s, starting in degraded mode: {}",
+                            config.startup_timeout_seconds,
+                            e
+                        );
+                        return false;
+                    }
+                    log::warn!("Database is not reachable yet, retrying in {}s: {}", delay.as_secs(), e);
+                    sleep(delay.min(deadline - now)).await;
+                    delay = (delay * 2).min(max_delay);
+                }
+            }
+        }
+    }
+
+    /// Runs migrations and index creation once per process, as soon as the database is reachable
+    async fn prepare(&self) {
+        if self.prepared.load(Ordering::Relaxed) {
+            return;
+        }
+
+        // Old documents still deserialize thanks to serde defaults, so a failed migration
+        // is retried on the next health check instead of stopping the bot
+        match self.run_migrations().await {
+            Ok(0) => {}
+            Ok(applied) => log::info!("Applied {} database migration(s)", applied),
+            Err(e) => {
+                log::error!("Database migration failed: {}", e);
+                return;
+            }
+        }
+        self.ensure_indexes().await;
+        self.prepared.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Spawns the task pinging the database every `health_check_interval_seconds`
+pub fn start_health_checker(database_service: DatabaseService, config: &DatabaseConfig) {
+    let interval = Duration::from_secs(config.health_check_interval_seconds.max(1));
+
+    tokio::spawn(async move {
+        loop {
+            sleep(interval).await;
+            database_service.check_health().await;
+        }
+    });
+}
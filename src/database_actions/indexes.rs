@@ -0,0 +1,45 @@
+use mongodb::{
+    bson::{doc, Document},
+    error::{ErrorKind, WriteFailure},
+    options::IndexOptions,
+    Collection, IndexModel,
+};
+
+use super::DatabaseServiceInner;
+
+/// Server error code for a write that violates a unique index
+const DUPLICATE_KEY: i32 = 11000;
+
+impl DatabaseServiceInner {
+    /// Creates the indexes the bot relies on; existing indexes are left as they are.
+    ///
+    /// A failing index is logged and skipped so one bad collection doesn't keep the bot down.
+    pub async fn ensure_indexes(&self) {
+        let days = self.collection.clone_with_type::<Document>();
+        ensure_index(&days, "date_unique", doc! { "date": 1 }, true).await;
+        ensure_index(&days, "poll_id", doc! { "polls.poll_id": 1 }, false).await;
+
+        ensure_index(&self.chats.clone_with_type(), "chat_id_unique", doc! { "chat_id": 1 }, true).await;
+        ensure_index(&self.users.clone_with_type(), "user_id_unique", doc! { "user_id": 1 }, true).await;
+        ensure_index(&self.job_runs.clone_with_type(), "job_slot_unique", doc! { "job": 1, "slot": 1 }, true).await;
+    }
+}
+
+async fn ensure_index(collection: &Collection<Document>, name: &str, keys: Document, unique: bool) {
+    let options = IndexOptions::builder().name(name.to_string()).unique(unique).build();
+    let model = IndexModel::builder().keys(keys).options(options).build();
+
+    match collection.create_index(model, None).await {
+        Ok(_) => log::debug!("Index {}.{} is in place", collection.name(), name),
+        Err(e) => log::error!("Failed to create index {}.{}: {}", collection.name(), name, e),
+    }
+}
+
+/// Whether the error is a unique index violation, e.g. from two upserts racing each other
+pub fn is_duplicate_key(error: &mongodb::error::Error) -> bool {
+    match error.kind.as_ref() {
+        ErrorKind::Write(WriteFailure::WriteError(e)) => e.code == DUPLICATE_KEY,
+        ErrorKind::Command(e) => e.code == DUPLICATE_KEY,
+        _ => false,
+    }
+}
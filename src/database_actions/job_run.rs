@@ -0,0 +1,11 @@
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+
+/// Marks a scheduled job slot as performed so restarts never repeat it
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JobRun {
+    pub job: String,
+    pub slot: DateTime,
+    pub started_at: DateTime,
+}
@@ -0,0 +1,356 @@
+//! Local journal of votes that couldn't be written while the database was down.
+//!
+//! Entries are appended as JSON lines and fsynced, so they survive a restart. Replaying
+//! first moves the journal aside, which lets new votes keep queueing in a fresh file; if
+//! the replay stops halfway, what's left is put back in front of them.
+
+use std::collections::{hash_map::Entry, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use super::{day::Day, DatabaseServiceInner, StoreError};
+
+/// A vote waiting to be written
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub user_id: i64,
+    /// Start of the day voted for, in milliseconds since the epoch
+    #[serde(default)]
+    pub day: Option<i64>,
+    /// Poll the vote was cast in, when the day it belongs to couldn't be looked up
+    #[serde(default)]
+    pub poll_id: Option<String>,
+    /// `Some(true)` for "late", `Some(false)` for "on time", `None` for a withdrawn vote
+    pub vote: Option<bool>,
+    /// When the vote was cast, in milliseconds since the epoch
+    pub at: i64,
+}
+
+pub struct VoteJournal {
+    path: PathBuf,
+    replaying_path: PathBuf,
+    /// Serializes appends with the moves done by replays
+    lock: Mutex<()>,
+}
+
+impl VoteJournal {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let mut replaying_path = path.clone().into_os_string();
+        replaying_path.push(".replaying");
+
+        Self {
+            path,
+            replaying_path: replaying_path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Appends `entry` and waits until it is on disk. The file is written on the blocking
+    /// thread pool, so a burst of votes during an outage doesn't stall the runtime
+    pub async fn append(&self, entry: &JournalEntry) -> io::Result<()> {
+        let line = serde_json::to_string(entry)? + "\n";
+        let _guard = self.lock.lock().await;
+        let path = self.path.clone();
+        blocking(move || {
+            let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+            file.write_all(line.as_bytes())?;
+            file.sync_data()
+        })
+        .await
+    }
+
+    /// Whether there is anything to replay
+    pub async fn is_empty(&self) -> bool {
+        let _guard = self.lock.lock().await;
+        !self.path.exists() && !self.replaying_path.exists()
+    }
+
+    /// Moves the journal aside and returns its entries in the order they were written.
+    ///
+    /// Entries left over from an interrupted replay come first. The caller must finish
+    /// with [`Self::finish_replay`].
+    pub async fn start_replay(&self) -> io::Result<Vec<JournalEntry>> {
+        let _guard = self.lock.lock().await;
+        let (path, replaying_path) = (self.path.clone(), self.replaying_path.clone());
+        blocking(move || {
+            if !replaying_path.exists() {
+                if !path.exists() {
+                    return Ok(Vec::new());
+                }
+                fs::rename(&path, &replaying_path)?;
+            } else if path.exists() {
+                // A previous replay was interrupted while new votes kept coming in
+                let mut entries = read_entries(&replaying_path)?;
+                entries.extend(read_entries(&path)?);
+                write_entries(&replaying_path, &entries)?;
+                fs::remove_file(&path)?;
+            }
+            read_entries(&replaying_path)
+        })
+        .await
+    }
+
+    /// Ends a replay, putting the entries that weren't written back in front of the journal
+    pub async fn finish_replay(&self, remaining: &[JournalEntry]) -> io::Result<()> {
+        let _guard = self.lock.lock().await;
+        let (path, replaying_path) = (self.path.clone(), self.replaying_path.clone());
+        let mut entries = remaining.to_vec();
+        blocking(move || {
+            if !entries.is_empty() {
+                if path.exists() {
+                    entries.extend(read_entries(&path)?);
+                }
+                write_entries(&path, &entries)?;
+            }
+            fs::remove_file(&replaying_path).or_else(|e| match e.kind() {
+                io::ErrorKind::NotFound => Ok(()),
+                _ => Err(e),
+            })
+        })
+        .await
+    }
+}
+
+impl DatabaseServiceInner {
+    /// Writes the journaled votes, returning how many were applied.
+    ///
+    /// Only the last vote of each user per day counts, and it is skipped if the user voted
+    /// again after the database came back. A vote the server rejects is dropped; on any
+    /// other failure the rest stays journaled.
+    pub async fn replay_journal(&self) -> Result<usize, StoreError> {
+        if self.journal.is_empty().await {
+            return Ok(0);
+        }
+        let entries = self
+            .journal
+            .start_replay()
+            .await
+            .map_err(|e| StoreError::Connection(e.into()))?;
+        log::info!("Replaying {} journaled vote(s)", entries.len());
+
+        let result = self.replay_entries(&entries).await;
+        let remaining = match &result {
+            Ok(_) => &[][..],
+            Err((index, _)) => &entries[*index..],
+        };
+        if let Err(e) = self.journal.finish_replay(remaining).await {
+            log::error!("Failed to update the vote journal: {}", e);
+        }
+        result.map_err(|(_, e)| e)
+    }
+
+    /// Applies entries in order; on failure returns the index of the first entry not applied
+    async fn replay_entries(&self, entries: &[JournalEntry]) -> Result<usize, (usize, StoreError)> {
+        // Resolve poll answers to days
+        let mut resolved = Vec::with_capacity(entries.len());
+        for (index, entry) in entries.iter().enumerate() {
+            let day = match (entry.day, &entry.poll_id) {
+                (Some(day), _) => DateTime::from_millis(day),
+                (None, Some(poll_id)) => match self.find_day_by_poll(poll_id).await {
+                    Ok(Some(day)) => day.date,
+                    Ok(None) => {
+                        log::warn!("Dropping journaled answer to unknown poll {}", poll_id);
+                        continue;
+                    }
+                    Err(e) => return Err((0, e)),
+                },
+                (None, None) => continue,
+            };
+            resolved.push((index, (entry.user_id, day)));
+        }
+
+        let mut days: HashMap<DateTime, Option<Day>> = HashMap::new();
+        let mut applied = 0;
+        for ((user_id, date), index) in latest_votes(resolved) {
+            let entry = &entries[index];
+            let at = DateTime::from_millis(entry.at);
+
+            if let Entry::Vacant(slot) = days.entry(date) {
+                let day = self.collection.find_one(mongodb::bson::doc! { "date": date }, None).await;
+                slot.insert(day.map_err(|e| (index, e.into()))?);
+            }
+            if has_newer_vote(days[&date].as_ref(), user_id, at) {
+                continue;
+            }
+
+            match self.write_vote(date, user_id, entry.vote, at).await {
+                Ok(()) => applied += 1,
+                // The server will refuse it again on every replay
+                Err(e @ StoreError::Rejected(_)) => {
+                    log::error!("Dropping journaled vote of user {} the database rejects: {}", user_id, e);
+                }
+                Err(e) => return Err((index, e)),
+            }
+        }
+        Ok(applied)
+    }
+}
+
+/// Keeps each user's last entry per day, given as (entry index, (user id, day)), in the
+/// order the entries were written
+fn latest_votes(resolved: Vec<(usize, (i64, DateTime))>) -> Vec<((i64, DateTime), usize)> {
+    let mut latest = HashMap::new();
+    for (index, key) in resolved {
+        latest.insert(key, index);
+    }
+    let mut pending: Vec<((i64, DateTime), usize)> = latest.into_iter().collect();
+    pending.sort_by_key(|(_, index)| *index);
+    pending
+}
+
+/// Whether `day` already has a vote change of `user_id` at or after `at`, i.e. the user
+/// voted again once the database was back
+fn has_newer_vote(day: Option<&Day>, user_id: i64, at: DateTime) -> bool {
+    day.is_some_and(|day| day.vote_log.iter().any(|event| event.user_id == user_id && event.at >= at))
+}
+
+/// Runs file operations on the blocking thread pool
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> io::Result<T> + Send + 'static) -> io::Result<T> {
+    tokio::task::spawn_blocking(f).await.map_err(io::Error::other)?
+}
+
+fn read_entries(path: &Path) -> io::Result<Vec<JournalEntry>> {
+    let mut entries = Vec::new();
+    for (index, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        // A crash in the middle of an append leaves a torn last line; it can't be recovered
+        match serde_json::from_str(&line) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => log::warn!("Skipping malformed line {} of {}: {}", index + 1, path.display(), e),
+        }
+    }
+    Ok(entries)
+}
+
+/// Replaces the file at `path` with `entries`, going through a temporary file
+fn write_entries(path: &Path, entries: &[JournalEntry]) -> io::Result<()> {
+    let mut temporary_path = path.to_path_buf().into_os_string();
+    temporary_path.push(".tmp");
+
+    let mut file = File::create(&temporary_path)?;
+    for entry in entries {
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    }
+    file.sync_data()?;
+    fs::rename(&temporary_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::day::VoteEvent;
+    use super::*;
+
+    fn entry(user_id: i64, vote: Option<bool>, at: i64) -> JournalEntry {
+        JournalEntry { user_id, day: Some(0), poll_id: None, vote, at }
+    }
+
+    fn day(events: &[(i64, i64)]) -> Day {
+        let mut day = Day::new(DateTime::from_millis(0));
+        day.vote_log = events
+            .iter()
+            .map(|&(user_id, at)| VoteEvent { user_id, vote: Some(true), at: DateTime::from_millis(at) })
+            .collect();
+        day
+    }
+
+    /// A journal in a fresh directory under the system temporary one
+    fn journal(name: &str) -> (VoteJournal, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("latebot-journal-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        (VoteJournal::new(dir.join("votes.jsonl")), dir)
+    }
+
+    #[test]
+    fn latest_votes_keep_the_last_vote_per_user_and_day() {
+        let (monday, tuesday) = (DateTime::from_millis(0), DateTime::from_millis(86_400_000));
+        let resolved = vec![
+            (0, (1, monday)),
+            (1, (2, monday)),
+            (2, (1, tuesday)),
+            (3, (1, monday)),
+            (4, (2, monday)),
+        ];
+        assert_eq!(latest_votes(resolved), vec![((1, tuesday), 2), ((1, monday), 3), ((2, monday), 4)]);
+    }
+
+    #[test]
+    fn latest_votes_skip_nothing_without_duplicates() {
+        let date = DateTime::from_millis(0);
+        assert_eq!(latest_votes(vec![(0, (1, date)), (2, (2, date))]), vec![((1, date), 0), ((2, date), 2)]);
+        assert!(latest_votes(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn newer_vote_of_the_same_user_wins() {
+        let at = DateTime::from_millis(1_000);
+        assert!(has_newer_vote(Some(&day(&[(1, 2_000)])), 1, at));
+        // A vote at the same moment is the journaled one, already written
+        assert!(has_newer_vote(Some(&day(&[(1, 1_000)])), 1, at));
+    }
+
+    #[test]
+    fn older_or_other_votes_are_replayed() {
+        let at = DateTime::from_millis(1_000);
+        assert!(!has_newer_vote(None, 1, at));
+        assert!(!has_newer_vote(Some(&day(&[])), 1, at));
+        assert!(!has_newer_vote(Some(&day(&[(1, 500)])), 1, at));
+        assert!(!has_newer_vote(Some(&day(&[(2, 2_000)])), 1, at));
+    }
+
+    #[tokio::test]
+    async fn entries_survive_a_replay_in_order() {
+        let (journal, dir) = journal("replay");
+        assert!(journal.is_empty().await);
+        assert!(journal.start_replay().await.unwrap().is_empty());
+
+        for at in 0..3 {
+            journal.append(&entry(1, Some(true), at)).await.unwrap();
+        }
+        let entries = journal.start_replay().await.unwrap();
+        assert_eq!(entries.iter().map(|e| e.at).collect::<Vec<_>>(), vec![0, 1, 2]);
+
+        // Votes cast during the replay queue behind the ones not written
+        journal.append(&entry(2, None, 3)).await.unwrap();
+        journal.finish_replay(&entries[1..]).await.unwrap();
+        let entries = journal.start_replay().await.unwrap();
+        assert_eq!(entries.iter().map(|e| e.at).collect::<Vec<_>>(), vec![1, 2, 3]);
+
+        journal.finish_replay(&[]).await.unwrap();
+        assert!(journal.is_empty().await);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn interrupted_replay_is_resumed_first() {
+        let (journal, dir) = journal("interrupted");
+        journal.append(&entry(1, Some(false), 0)).await.unwrap();
+        journal.start_replay().await.unwrap();
+        // The bot stopped before finishing the replay, and a vote came in after restarting
+        journal.append(&entry(2, Some(true), 1)).await.unwrap();
+
+        let entries = journal.start_replay().await.unwrap();
+        assert_eq!(entries.iter().map(|e| e.user_id).collect::<Vec<_>>(), vec![1, 2]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn malformed_lines_are_skipped() {
+        let (journal, dir) = journal("malformed");
+        let line = serde_json::to_string(&entry(1, Some(true), 0)).unwrap();
+        fs::write(&journal.path, format!("{}\n\n{{\"user_id\": 2, \"vo\n", line)).unwrap();
+        let entries = read_entries(&journal.path).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].user_id, 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
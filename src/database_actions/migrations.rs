@@ -0,0 +1,288 @@
+//! Versioning of the stored documents.
+//!
+//! The version the database has been upgraded to is kept in the `meta` collection, and every
+//! day document carries the `schema_version` it was last written or migrated with. Migrations
+//! are idempotent, so a run interrupted halfway is simply repeated on the next start.
+
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, from_document, to_document, Bson, Document},
+    options::{FindOptions, UpdateOptions},
+};
+use serde::{Deserialize, Serialize};
+
+use super::{day::Day, DatabaseServiceInner, StoreError};
+
+/// Layout version written by this build
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// `_id` of the document in `meta` holding the schema version
+const SCHEMA_META_ID: &str = "schema";
+
+/// Every known migration: the version it upgrades to and what it does
+const MIGRATIONS: &[(u32, &str)] = &[
+    (1, "fill in fields missing from day documents"),
+    (2, "merge duplicate documents of the same day before the unique index on date"),
+];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SchemaMeta {
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub version: u32,
+}
+
+impl DatabaseServiceInner {
+    /// Version the database has been upgraded to; `0` for a database that predates versioning
+    pub async fn schema_version(&self) -> Result<u32, StoreError> {
+        let meta = self.meta.find_one(doc! { "_id": SCHEMA_META_ID }, None).await?;
+        Ok(meta.map(|meta| meta.version).unwrap_or_default())
+    }
+
+    /// Applies the migrations the database hasn't seen yet, returning how many were applied
+    pub async fn run_migrations(&self) -> Result<usize, StoreError> {
+        let current = self.schema_version().await?;
+        if current > SCHEMA_VERSION {
+            log::warn!(
+                "Database schema version {} is newer than {} supported by this build",
+                current,
+                SCHEMA_VERSION
+            );
+            return Ok(0);
+        }
+
+        let mut applied = 0;
+        for &(version, description) in MIGRATIONS.iter().filter(|(version, _)| *version > current) {
+            log::info!("Applying database migration {}: {}", version, description);
+            self.migrate_to(version).await?;
+            self.stamp_days(version).await?;
+            self.meta
+                .update_one(
+                    doc! { "_id": SCHEMA_META_ID },
+                    doc! { "$set": { "version": version } },
+                    UpdateOptions::builder().upsert(true).build(),
+                )
+                .await?;
+            applied += 1;
+        }
+        Ok(applied)
+    }
+
+    async fn migrate_to(&self, version: u32) -> Result<(), StoreError> {
+        match version {
+            1 => {
+                let days = self.collection.clone_with_type::<Document>();
+                for field in ["votes_yes", "votes_no", "announced_milestones", "polls", "vote_log"] {
+                    days.update_many(
+                        doc! { field: { "$exists": false } },
+                        doc! { "$set": { field: [] } },
+                        None,
+                    )
+                    .await?;
+                }
+                Ok(())
+            }
+            2 => self.merge_duplicate_days().await,
+            _ => unreachable!("no migration to schema version {}", version),
+        }
+    }
+
+    /// Folds every group of day documents sharing a date into the oldest one of the group
+    async fn merge_duplicate_days(&self) -> Result<(), StoreError> {
+        let days = self.collection.clone_with_type::<Document>();
+        let pipeline = [
+            doc! { "$group": { "_id": "$date", "count": { "$sum": 1 } } },
+            doc! { "$match": { "count": { "$gt": 1 } } },
+        ];
+        let duplicated: Vec<Document> = days.aggregate(pipeline, None).await?.try_collect().await?;
+
+        for group in duplicated {
+            let Some(date) = group.get("_id").cloned() else {
+                continue;
+            };
+            let options = FindOptions::builder().sort(doc! { "_id": 1 }).build();
+            let documents: Vec<Document> = days.find(doc! { "date": &date }, options).await?.try_collect().await?;
+
+            let (ids, copies) = readable_copies(documents);
+            let Some((keep, extra)) = ids.split_first() else {
+                continue;
+            };
+            if extra.is_empty() {
+                continue;
+            }
+
+            log::info!("Merging {} documents of day {}", copies.len(), date);
+            let merged = merge_days(copies);
+            days.replace_one(doc! { "_id": keep }, to_document(&merged)?, None).await?;
+            days.delete_many(doc! { "_id": { "$in": extra } }, None).await?;
+        }
+        Ok(())
+    }
+
+    /// Marks days older than `version` as migrated to it
+    async fn stamp_days(&self, version: u32) -> Result<(), StoreError> {
+        let filter = doc! {
+            "$or": [
+                { "schema_version": { "$exists": false } },
+                { "schema_version": { "$lt": version } }
+            ]
+        };
+        let update = doc! {
+            "$set": { "schema_version": version }
+        };
+
+        self.collection.update_many(filter, update, None).await?;
+        Ok(())
+    }
+}
+
+/// Splits copies of one day into their ids and contents, leaving out the ones that can't be
+/// read. Those are logged and stay in the collection, so one damaged document doesn't keep
+/// the other days from being merged
+fn readable_copies(documents: Vec<Document>) -> (Vec<Bson>, Vec<Day>) {
+    let mut ids = Vec::new();
+    let mut copies = Vec::new();
+    for document in documents {
+        let id = document.get("_id").cloned().unwrap_or(Bson::Null);
+        match from_document::<Day>(document) {
+            Ok(day) => {
+                ids.push(id);
+                copies.push(day);
+            }
+            Err(e) => log::error!("Leaving out unreadable day document {} while merging duplicates: {}", id, e),
+        }
+    }
+    (ids, copies)
+}
+
+/// Combines copies of one day, oldest first.
+///
+/// A voter found on both sides keeps the side of their latest logged vote, or of the
+/// oldest copy if the log doesn't mention them.
+fn merge_days(copies: Vec<Day>) -> Day {
+    let mut copies = copies.into_iter();
+    let mut merged = copies.next().expect("at least one copy of a day");
+    for copy in copies {
+        for user_id in copy.votes_yes {
+            if !merged.votes_yes.contains(&user_id) && !merged.votes_no.contains(&user_id) {
+                merged.votes_yes.push(user_id);
+            }
+        }
+        for user_id in copy.votes_no {
+            if !merged.votes_yes.contains(&user_id) && !merged.votes_no.contains(&user_id) {
+                merged.votes_no.push(user_id);
+            }
+        }
+        for key in copy.announced_milestones {
+            if !merged.announced_milestones.contains(&key) {
+                merged.announced_milestones.push(key);
+            }
+        }
+        merged.polls.extend(copy.polls);
+        merged.vote_log.extend(copy.vote_log);
+    }
+    merged.vote_log.sort_by_key(|event| event.at);
+
+    for event in merged.vote_log.clone() {
+        merged.votes_yes.retain(|id| *id != event.user_id);
+        merged.votes_no.retain(|id| *id != event.user_id);
+        match event.vote {
+            Some(true) => merged.votes_yes.push(event.user_id),
+            Some(false) => merged.votes_no.push(event.user_id),
+            None => {}
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::DateTime;
+
+    use super::super::day::{PollRef, VoteEvent};
+    use super::*;
+
+    fn copy(votes_yes: &[i64], votes_no: &[i64]) -> Day {
+        let mut day = Day::new(DateTime::from_millis(0));
+        day.votes_yes = votes_yes.to_vec();
+        day.votes_no = votes_no.to_vec();
+        day
+    }
+
+    fn event(user_id: i64, vote: Option<bool>, at: i64) -> VoteEvent {
+        VoteEvent { user_id, vote, at: DateTime::from_millis(at) }
+    }
+
+    #[test]
+    fn unreadable_copies_are_left_out() {
+        let date = DateTime::from_millis(0);
+        let documents = vec![
+            doc! { "_id": 1, "date": date, "votes_yes": [1] },
+            doc! { "_id": 2, "date": date, "votes_yes": "not a list" },
+            doc! { "_id": 3, "votes_no": [2] },
+            doc! { "_id": 4, "date": date, "votes_no": [3] },
+        ];
+        let (ids, copies) = readable_copies(documents);
+        assert_eq!(ids, vec![Bson::Int32(1), Bson::Int32(4)]);
+
+        let merged = merge_days(copies);
+        assert_eq!((merged.votes_yes, merged.votes_no), (vec![1], vec![3]));
+    }
+
+    #[test]
+    fn single_copy_is_kept_as_is() {
+        let merged = merge_days(vec![copy(&[1, 2], &[3])]);
+        assert_eq!((merged.votes_yes, merged.votes_no), (vec![1, 2], vec![3]));
+    }
+
+    #[test]
+    fn vote_sets_are_united_without_duplicates() {
+        let merged = merge_days(vec![copy(&[1, 2], &[3]), copy(&[2, 4], &[5]), copy(&[], &[3, 6])]);
+        assert_eq!(merged.votes_yes, vec![1, 2, 4]);
+        assert_eq!(merged.votes_no, vec![3, 5, 6]);
+    }
+
+    #[test]
+    fn oldest_copy_wins_for_voters_missing_from_the_log() {
+        let merged = merge_days(vec![copy(&[1], &[2]), copy(&[2], &[1])]);
+        assert_eq!((merged.votes_yes, merged.votes_no), (vec![1], vec![2]));
+    }
+
+    #[test]
+    fn latest_logged_vote_decides_the_side() {
+        let mut older = copy(&[1, 2], &[]);
+        older.vote_log = vec![event(1, Some(true), 10), event(2, Some(true), 30)];
+        let mut newer = copy(&[], &[1, 2]);
+        newer.vote_log = vec![event(1, Some(false), 20), event(2, Some(false), 5)];
+
+        let merged = merge_days(vec![older, newer]);
+        assert_eq!((merged.votes_yes, merged.votes_no), (vec![2], vec![1]));
+        let times: Vec<i64> = merged.vote_log.iter().map(|event| event.at.timestamp_millis()).collect();
+        assert_eq!(times, vec![5, 10, 20, 30]);
+    }
+
+    #[test]
+    fn withdrawn_vote_in_the_log_removes_the_voter() {
+        let mut older = copy(&[1], &[]);
+        older.vote_log = vec![event(1, Some(true), 10)];
+        let mut newer = copy(&[1], &[]);
+        newer.vote_log = vec![event(1, None, 20)];
+
+        let merged = merge_days(vec![older, newer]);
+        assert!(merged.votes_yes.is_empty() && merged.votes_no.is_empty());
+    }
+
+    #[test]
+    fn milestones_and_polls_are_combined() {
+        let mut older = copy(&[], &[]);
+        older.announced_milestones = vec!["first_vote".to_string()];
+        let mut newer = copy(&[], &[]);
+        newer.announced_milestones = vec!["first_vote".to_string(), "late_3".to_string()];
+        newer.polls = vec![PollRef { poll_id: "p1".to_string(), chat_id: -1, message_id: 7 }];
+
+        let merged = merge_days(vec![older, newer]);
+        assert_eq!(merged.announced_milestones, vec!["first_vote", "late_3"]);
+        assert_eq!(merged.polls.len(), 1);
+        assert_eq!(merged.date, DateTime::from_millis(0));
+    }
+}
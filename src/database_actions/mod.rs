@@ -1,63 +1,226 @@
-use std::sync::Arc;
+use std::sync::{atomic::AtomicBool, Arc};
+use std::time::Duration;
 
-use day::Day;
-use mongodb::{bson::doc, options::ClientOptions, Client, Collection};
+use futures::TryStreamExt;
+use chat_settings::ChatSettings;
+use day::{Day, PollRef};
+use job_run::JobRun;
+use journal::{JournalEntry, VoteJournal};
+use migrations::SchemaMeta;
+use today_cache::{Lookup, TodayCache};
+use user_profile::UserProfile;
 
+pub use error::StoreError;
+use mongodb::{
+    bson::{doc, DateTime, Document},
+    options::{
+        ClientOptions, FindOneAndUpdateOptions, FindOneOptions, FindOptions, ReturnDocument, UpdateOptions,
+    },
+    Client, Collection, Database,
+};
+
+use crate::config::DatabaseConfig;
+
+pub mod chat_settings;
 pub mod day;
+pub mod error;
+pub mod health;
+pub mod indexes;
+pub mod job_run;
+pub mod journal;
+pub mod migrations;
+pub mod today_cache;
+pub mod user_profile;
 
 pub type DatabaseService = Arc<DatabaseServiceInner>;
 
+/// What happened to a vote
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoteReceipt {
+    /// The vote is in the database
+    Recorded,
+    /// The database is unreachable; the vote is journaled and will be written once it's back
+    Queued,
+}
+
 pub struct DatabaseServiceInner {
     collection: Collection<Day>,
+    chats: Collection<ChatSettings>,
+    users: Collection<UserProfile>,
+    job_runs: Collection<JobRun>,
+    meta: Collection<SchemaMeta>,
+    db: Database,
+    /// Result of the last health check, see [`health`]
+    available: AtomicBool,
+    /// Whether migrations have run against the current database
+    prepared: AtomicBool,
+    /// Votes waiting for the database to come back
+    journal: VoteJournal,
+    today_cache: TodayCache,
 }
 
 impl DatabaseServiceInner {
-    pub async fn new(connection_uri: &str) -> DatabaseService {
-        let client_options = ClientOptions::parse(connection_uri)
-            .await
-            .expect("Failed to parse MongoDB options");
-        let client =
-            Client::with_options(client_options).expect("Failed to initialize MongoDB client");
+    /// Creates the client; no connection is made until the first operation
+    pub async fn new(config: &DatabaseConfig) -> Result<DatabaseService, StoreError> {
+        let mut client_options = ClientOptions::parse(config.connection_uri.expose()).await?;
+        client_options.server_selection_timeout =
+            Some(Duration::from_secs(config.server_selection_timeout_seconds));
+        let client = Client::with_options(client_options)?;
         let db = client.database("latebot");
         let collection = db.collection::<Day>("days");
+        let chats = db.collection::<ChatSettings>("chats");
+        let users = db.collection::<UserProfile>("users");
+        let job_runs = db.collection::<JobRun>("job_runs");
+        let meta = db.collection::<SchemaMeta>("meta");
 
-        let inner = DatabaseServiceInner { collection };
+        let inner = DatabaseServiceInner {
+            collection,
+            chats,
+            users,
+            job_runs,
+            meta,
+            db,
+            available: AtomicBool::new(false),
+            prepared: AtomicBool::new(false),
+            journal: VoteJournal::new(&config.vote_journal_path),
+            today_cache: TodayCache::new(Duration::from_millis(config.today_cache_ttl_ms)),
+        };
 
-        Arc::new(inner)
+        Ok(Arc::new(inner))
     }
 
-    pub async fn check_today_document(&self) -> Result<Day, mongodb::error::Error> {
-        let now = mongodb::bson::DateTime::now();
-        let today_start = mongodb::bson::DateTime::from_millis(
-            now.timestamp_millis() - (now.timestamp_millis() % 86400000)
-        );
-        
+    /// Today's document, served from the cache when it is known to be current
+    pub async fn check_today_document(&self) -> Result<Day, StoreError> {
+        let today_start = Day::today_start();
+
+        match self.today_cache.lookup(today_start) {
+            Lookup::Fresh(day) => return Ok(day),
+            Lookup::Stale(version) => {
+                // Only a changed document is sent back, so an unchanged day costs no transfer
+                let filter = doc! {
+                    "date": today_start,
+                    "version": { "$ne": version }
+                };
+                match self.collection.find_one(filter, None).await? {
+                    Some(day) => {
+                        self.today_cache.store(&day);
+                        return Ok(day);
+                    }
+                    None => {
+                        self.today_cache.touch(today_start);
+                        if let Lookup::Fresh(day) = self.today_cache.lookup(today_start) {
+                            return Ok(day);
+                        }
+                    }
+                }
+            }
+            Lookup::Missing => {}
+        }
+
+        self.get_or_create_day(today_start).await
+    }
+
+    /// Returns the day starting at `date`, atomically creating it if it doesn't exist yet
+    pub async fn get_or_create_day(&self, date: DateTime) -> Result<Day, StoreError> {
         let filter = doc! {
-            "date": today_start
+            "date": date
         };
-        
-        match self.collection.find_one(filter, None).await.unwrap() {
-            Some(day) => Ok(day),
-            None => {
-                let new_day = Day {
-                    date: today_start,
-                    votes_yes: Vec::new(),
-                    votes_no: Vec::new(),
-                };
-                self.collection.insert_one(&new_day, None).await.unwrap();
-                Ok(new_day)
+        let update = doc! {
+            "$setOnInsert": mongodb::bson::to_document(&Day::new(date))?
+        };
+
+        self.update_day(filter, update, true)
+            .await?
+            .ok_or_else(|| StoreError::NotFound(format!("day {}", date)))
+    }
+
+    pub async fn vote(&self, user_id: i64, vote_yes: bool) -> Result<VoteReceipt, StoreError> {
+        self.vote_on_day(Day::today_start(), user_id, vote_yes).await
+    }
+
+    /// Records a vote into the day starting at `date`, replacing the user's previous vote.
+    ///
+    /// While the database is unreachable the vote goes to the local journal instead.
+    pub async fn vote_on_day(&self, date: mongodb::bson::DateTime, user_id: i64, vote_yes: bool) -> Result<VoteReceipt, StoreError> {
+        self.write_or_queue(date, user_id, Some(vote_yes)).await
+    }
+
+    /// Withdraws the user's vote from the day starting at `date`, whichever side it was on
+    pub async fn remove_vote(&self, date: mongodb::bson::DateTime, user_id: i64) -> Result<VoteReceipt, StoreError> {
+        self.write_or_queue(date, user_id, None).await
+    }
+
+    /// Journals an answer to a poll whose day can't be looked up while the database is down
+    pub async fn queue_poll_answer(&self, poll_id: &str, user_id: i64, vote: Option<bool>) -> Result<VoteReceipt, StoreError> {
+        let entry = JournalEntry {
+            user_id,
+            day: None,
+            poll_id: Some(poll_id.to_string()),
+            vote,
+            at: DateTime::now().timestamp_millis(),
+        };
+        self.queue(&entry).await
+    }
+
+    async fn write_or_queue(&self, date: DateTime, user_id: i64, vote: Option<bool>) -> Result<VoteReceipt, StoreError> {
+        let at = DateTime::now();
+        let entry = JournalEntry {
+            user_id,
+            day: Some(date.timestamp_millis()),
+            poll_id: None,
+            vote,
+            at: at.timestamp_millis(),
+        };
+        if !self.is_available() {
+            return self.queue(&entry).await;
+        }
+
+        match self.write_vote(date, user_id, vote, at).await {
+            Ok(()) => Ok(VoteReceipt::Recorded),
+            Err(e @ StoreError::Connection(_)) => {
+                log::warn!("Failed to write vote of user {}, queueing it: {}", user_id, e);
+                self.mark_unavailable();
+                self.queue(&entry).await.map_err(|_| e)
             }
+            Err(e) => Err(e),
         }
     }
 
-    pub async fn vote(&self, user_id: i64, vote_yes: bool) -> Result<(), mongodb::error::Error> {
-        let now = mongodb::bson::DateTime::now();
-        let today_start = mongodb::bson::DateTime::from_millis(
-            now.timestamp_millis() - (now.timestamp_millis() % 86400000)
-        );
+    async fn queue(&self, entry: &JournalEntry) -> Result<VoteReceipt, StoreError> {
+        match self.journal.append(entry).await {
+            Ok(()) => {
+                log::info!("Queued vote of user {} until the database is back", entry.user_id);
+                Ok(VoteReceipt::Queued)
+            }
+            Err(e) => {
+                log::error!("Failed to journal vote of user {}: {}", entry.user_id, e);
+                Err(StoreError::Connection(e.into()))
+            }
+        }
+    }
 
+    /// Writes a vote change cast at `at`; `None` withdraws the vote
+    async fn write_vote(&self, date: DateTime, user_id: i64, vote: Option<bool>, at: DateTime) -> Result<(), StoreError> {
         let filter = doc! {
-            "date": today_start
+            "date": date
+        };
+
+        let Some(vote_yes) = vote else {
+            let update = doc! {
+                "$pull": {
+                    "votes_yes": user_id,
+                    "votes_no": user_id
+                },
+                "$push": {
+                    "vote_log": { "user_id": user_id, "vote": null, "at": at }
+                },
+                "$inc": {
+                    "version": 1
+                }
+            };
+
+            self.update_day(filter, update, false).await?;
+            return Ok(());
         };
 
         // Определяем, какие поля обновлять в зависимости от голоса
@@ -74,28 +237,157 @@ impl DatabaseServiceInner {
             },
             "$pull": {
                 remove_from_field: user_id
+            },
+            "$push": {
+                "vote_log": { "user_id": user_id, "vote": vote_yes, "at": at }
+            },
+            "$inc": {
+                "version": 1
+            },
+            "$setOnInsert": {
+                "schema_version": migrations::SCHEMA_VERSION
             }
         };
+        // Голос создаёт день, если его ещё нет; уникальный индекс не даст создать второй
+        self.update_day(filter, update, true).await?;
+        Ok(())
+    }
 
-        self.collection.update_one(filter, update, None).await?;
+    /// Remembers a poll posted for the day starting at `date`
+    pub async fn attach_poll(&self, date: mongodb::bson::DateTime, poll: &PollRef) -> Result<(), StoreError> {
+        let filter = doc! {
+            "date": date
+        };
+        let update = doc! {
+            "$push": {
+                "polls": {
+                    "poll_id": &poll.poll_id,
+                    "chat_id": poll.chat_id,
+                    "message_id": poll.message_id
+                }
+            },
+            "$inc": {
+                "version": 1
+            }
+        };
+
+        self.update_day(filter, update, false).await?;
         Ok(())
     }
 
-    pub async fn get_day_stats(&self, date: mongodb::bson::DateTime) -> Result<Day, mongodb::error::Error> {
+    /// Finds the day a native poll was posted for
+    pub async fn find_day_by_poll(&self, poll_id: &str) -> Result<Option<Day>, StoreError> {
+        let filter = doc! {
+            "polls.poll_id": poll_id
+        };
+
+        Ok(self.collection.find_one(filter, None).await?)
+    }
+
+    pub async fn get_day_stats(&self, date: mongodb::bson::DateTime) -> Result<Day, StoreError> {
         let filter = doc! {
             "date": date
         };
         
         match self.collection.find_one(filter, None).await? {
             Some(day) => Ok(day),
-            None => Err(mongodb::error::Error::from(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                "Документ за указанную дату не найден"
-            )))
+            None => Err(StoreError::NotFound(format!("day {}", date)))
         }
     }
 
-    pub async fn get_total_late_days(&self) -> Result<i32, mongodb::error::Error> {
+    /// Days in `[from, to)`, oldest first
+    pub async fn get_days_in_range(&self, from: DateTime, to: DateTime) -> Result<Vec<Day>, StoreError> {
+        let filter = doc! {
+            "date": { "$gte": from, "$lt": to }
+        };
+        let options = FindOptions::builder().sort(doc! { "date": 1 }).build();
+
+        Ok(self.collection.find(filter, options).await?.try_collect().await?)
+    }
+
+    /// The latest day in `[from, to)` that somebody voted on. Days whose document was only
+    /// created by a read, e.g. `/stats` or a scheduled post, don't count
+    pub async fn get_latest_voted_day_in_range(&self, from: DateTime, to: DateTime) -> Result<Option<Day>, StoreError> {
+        let options = FindOneOptions::builder().sort(doc! { "date": -1 }).build();
+        Ok(self.collection.find_one(voted_days_filter(from, to), options).await?)
+    }
+
+    /// Adds historical votes to the day starting at `date`, creating the day if needed.
+    ///
+    /// Voters already present on the same side are not duplicated; the caller is
+    /// responsible for not adding a voter to both sides.
+    pub async fn merge_votes(&self, date: DateTime, votes_yes: &[i64], votes_no: &[i64]) -> Result<(), StoreError> {
+        let filter = doc! {
+            "date": date
+        };
+        let update = doc! {
+            "$addToSet": {
+                "votes_yes": { "$each": votes_yes },
+                "votes_no": { "$each": votes_no }
+            },
+            "$inc": {
+                "version": 1
+            },
+            "$setOnInsert": {
+                "schema_version": migrations::SCHEMA_VERSION
+            }
+        };
+
+        self.update_day(filter, update, true).await?;
+        Ok(())
+    }
+
+    /// Clears the votes and announcements of the day starting at `date`; its polls are kept
+    pub async fn reset_day(&self, date: DateTime) -> Result<Day, StoreError> {
+        let filter = doc! {
+            "date": date
+        };
+        let update = doc! {
+            "$set": {
+                "votes_yes": [],
+                "votes_no": [],
+                "vote_log": [],
+                "announced_milestones": []
+            },
+            "$inc": {
+                "version": 1
+            }
+        };
+
+        self.update_day(filter, update, false)
+            .await?
+            .ok_or_else(|| StoreError::NotFound(format!("day {}", date)))
+    }
+
+    /// Applies `update` to a day and returns the updated document, which also refreshes
+    /// the cache of today's day. With `upsert` the day is created if needed.
+    ///
+    /// Two upserts racing for the same new day make one of them hit the unique index;
+    /// by then the day exists, so the retry simply updates it.
+    async fn update_day(
+        &self,
+        filter: mongodb::bson::Document,
+        update: mongodb::bson::Document,
+        upsert: bool,
+    ) -> Result<Option<Day>, StoreError> {
+        let options = FindOneAndUpdateOptions::builder()
+            .upsert(upsert)
+            .return_document(ReturnDocument::After)
+            .build();
+
+        let day = match self.collection.find_one_and_update(filter.clone(), update.clone(), options.clone()).await {
+            Err(e) if upsert && indexes::is_duplicate_key(&e) => {
+                self.collection.find_one_and_update(filter, update, options).await?
+            }
+            result => result?,
+        };
+        if let Some(day) = &day {
+            self.today_cache.store(day);
+        }
+        Ok(day)
+    }
+
+    pub async fn get_total_late_days(&self) -> Result<i32, StoreError> {
         let filter = doc! {
             "votes_yes": { "$exists": true, "$ne": [] }
         };
@@ -103,4 +395,126 @@ impl DatabaseServiceInner {
         let count = self.collection.count_documents(filter, None).await?;
         Ok(count as i32)
     }
+
+    /// Atomically marks milestone `key` as announced for the day starting at `date`.
+    ///
+    /// Returns `true` only for the caller that claimed it first.
+    pub async fn claim_milestone(&self, date: mongodb::bson::DateTime, key: &str) -> Result<bool, StoreError> {
+        let filter = doc! {
+            "date": date,
+            "announced_milestones": { "$ne": key }
+        };
+        let update = doc! {
+            "$push": {
+                "announced_milestones": key
+            },
+            "$inc": {
+                "version": 1
+            }
+        };
+
+        Ok(self.update_day(filter, update, false).await?.is_some())
+    }
+
+    pub async fn get_chat_language(&self, chat_id: i64) -> Result<Option<String>, StoreError> {
+        let filter = doc! {
+            "chat_id": chat_id
+        };
+
+        let settings = self.chats.find_one(filter, None).await?;
+        Ok(settings.and_then(|s| s.language))
+    }
+
+    pub async fn set_chat_language(&self, chat_id: i64, language: Option<&str>) -> Result<(), StoreError> {
+        let filter = doc! {
+            "chat_id": chat_id
+        };
+        let update = doc! {
+            "$set": {
+                "language": language
+            }
+        };
+        let options = UpdateOptions::builder().upsert(true).build();
+
+        self.chats.update_one(filter, update, options).await?;
+        Ok(())
+    }
+
+    /// Number of days that have a document, i.e. days anyone voted or a poll was posted
+    pub async fn get_total_days(&self) -> Result<u64, StoreError> {
+        Ok(self.collection.count_documents(doc! {}, None).await?)
+    }
+
+    /// Stores the latest name and username of a user
+    pub async fn remember_user(&self, profile: &UserProfile) -> Result<(), StoreError> {
+        let filter = doc! {
+            "user_id": profile.user_id
+        };
+        let update = doc! {
+            "$set": {
+                "first_name": &profile.first_name,
+                "last_name": &profile.last_name,
+                "username": &profile.username
+            }
+        };
+        let options = UpdateOptions::builder().upsert(true).build();
+
+        self.users.update_one(filter, update, options).await?;
+        Ok(())
+    }
+
+    pub async fn get_user_profiles(&self, user_ids: &[i64]) -> Result<Vec<UserProfile>, StoreError> {
+        let filter = doc! {
+            "user_id": { "$in": user_ids }
+        };
+
+        Ok(self.users.find(filter, None).await?.try_collect().await?)
+    }
+
+    /// Records that `job` is being run for `slot`.
+    ///
+    /// Returns `true` only if nobody has claimed this slot before.
+    pub async fn claim_job_run(&self, job: &str, slot: DateTime) -> Result<bool, StoreError> {
+        let filter = doc! {
+            "job": job,
+            "slot": slot
+        };
+        let update = doc! {
+            "$setOnInsert": {
+                "started_at": DateTime::now()
+            }
+        };
+        let options = UpdateOptions::builder().upsert(true).build();
+
+        let result = self.job_runs.update_one(filter, update, options).await?;
+        Ok(result.upserted_id.is_some())
+    }
+}
+
+/// Matches the days in `[from, to)` with at least one vote
+fn voted_days_filter(from: DateTime, to: DateTime) -> Document {
+    doc! {
+        "date": { "$gte": from, "$lt": to },
+        "$or": [
+            { "votes_yes": { "$ne": [] } },
+            { "votes_no": { "$ne": [] } }
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn voted_days_filter_requires_a_vote_within_the_range() {
+        let (from, to) = (DateTime::from_millis(0), DateTime::from_millis(86_400_000));
+        let filter = voted_days_filter(from, to);
+        assert_eq!(filter.get_document("date").unwrap(), &doc! { "$gte": from, "$lt": to });
+
+        let either_side = filter.get_array("$or").unwrap();
+        assert_eq!(either_side.len(), 2);
+        assert!(either_side.contains(&doc! { "votes_yes": { "$ne": [] } }.into()));
+        assert!(either_side.contains(&doc! { "votes_no": { "$ne": [] } }.into()));
+    }
 }
@@ -0,0 +1,83 @@
+//! Write-through cache of the current day's document.
+//!
+//! Every write to a day returns the updated document, which replaces the cached one, so an
+//! instance always sees its own votes immediately. Writes from other instances are noticed
+//! once the entry is older than the TTL: the day is then re-read only if its `version`
+//! changed. A new UTC day never matches the cached date, so rollover needs no timer.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use mongodb::bson::DateTime;
+
+use super::day::Day;
+
+pub struct TodayCache {
+    ttl: Duration,
+    entry: Mutex<Option<Cached>>,
+}
+
+struct Cached {
+    day: Day,
+    checked_at: Instant,
+}
+
+/// What the cache knows about a day
+pub enum Lookup {
+    /// Recent enough to use as is
+    Fresh(Day),
+    /// Needs revalidation against the stored `version`
+    Stale(i64),
+    Missing,
+}
+
+impl TodayCache {
+    /// A zero `ttl` disables the cache
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entry: Mutex::new(None),
+        }
+    }
+
+    pub fn lookup(&self, date: DateTime) -> Lookup {
+        let entry = self.entry.lock().unwrap_or_else(|e| e.into_inner());
+        match entry.as_ref() {
+            Some(cached) if cached.day.date == date => {
+                if cached.checked_at.elapsed() < self.ttl {
+                    Lookup::Fresh(cached.day.clone())
+                } else {
+                    Lookup::Stale(cached.day.version)
+                }
+            }
+            _ => Lookup::Missing,
+        }
+    }
+
+    /// Keeps `day` if it is today's and not older than the cached copy
+    pub fn store(&self, day: &Day) {
+        if self.ttl.is_zero() || day.date != Day::today_start() {
+            return;
+        }
+
+        let mut entry = self.entry.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(cached) = entry.as_ref() {
+            // Concurrent writes may finish out of order; the version tells which one is newer
+            if cached.day.date == day.date && cached.day.version > day.version {
+                return;
+            }
+        }
+        *entry = Some(Cached {
+            day: day.clone(),
+            checked_at: Instant::now(),
+        });
+    }
+
+    /// Marks the cached day as just confirmed to be current
+    pub fn touch(&self, date: DateTime) {
+        let mut entry = self.entry.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(cached) = entry.as_mut().filter(|cached| cached.day.date == date) {
+            cached.checked_at = Instant::now();
+        }
+    }
+}
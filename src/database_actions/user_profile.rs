@@ -0,0 +1,21 @@
+use serde::{Deserialize, Serialize};
+
+
+/// Last known Telegram identity of a voter, used to mention them in posts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserProfile {
+    pub user_id: i64,
+    #[serde(default)]
+    pub first_name: String,
+    pub last_name: Option<String>,
+    pub username: Option<String>,
+}
+
+impl UserProfile {
+    pub fn display_name(&self) -> String {
+        match &self.last_name {
+            Some(last_name) => format!("{} {}", self.first_name, last_name),
+            None => self.first_name.clone(),
+        }
+    }
+}
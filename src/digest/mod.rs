@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+
+use chrono::{Datelike, Days, Months, NaiveDate, Weekday};
+use teloxide::{prelude::*, types::InputFile, RequestError};
+
+use crate::charts::{render_calendar_heatmap, ChartError};
+use crate::database_actions::{day::Day, user_profile::UserProfile, DatabaseService, StoreError};
+use crate::localization::{tr, tr_args, tr_plural, Lang};
+
+/// How many voters the digest lists
+const TOP_VOTERS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DigestPeriod {
+    Week,
+    Month,
+}
+
+impl DigestPeriod {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "week" => Some(DigestPeriod::Week),
+            "month" => Some(DigestPeriod::Month),
+            _ => None,
+        }
+    }
+
+    /// The last complete period before the one containing `today`, as `[start, end)`
+    pub fn last_complete(self, today: NaiveDate) -> (NaiveDate, NaiveDate) {
+        match self {
+            DigestPeriod::Week => {
+                let this_week = today - Days::new(today.weekday().num_days_from_monday() as u64);
+                (this_week - Days::new(7), this_week)
+            }
+            DigestPeriod::Month => {
+                let this_month = today.with_day(1).unwrap_or(today);
+                (this_month - Months::new(1), this_month)
+            }
+        }
+    }
+
+    /// The period right before `[start, end)`
+    fn previous(self, start: NaiveDate) -> (NaiveDate, NaiveDate) {
+        match self {
+            DigestPeriod::Week => (start - Days::new(7), start),
+            DigestPeriod::Month => (start - Months::new(1), start),
+        }
+    }
+}
+
+/// Aggregated statistics for one digest period
+#[derive(Debug, Clone)]
+pub struct Digest {
+    pub period: DigestPeriod,
+    pub start: NaiveDate,
+    /// Exclusive end of the period
+    pub end: NaiveDate,
+    pub tracked_days: usize,
+    pub late_days: usize,
+    pub previous_late_days: usize,
+    /// Voters with the most votes in the period and their vote counts
+    pub top_voters: Vec<(Voter, usize)>,
+    /// Longest run of consecutive tracked days with a "late" verdict
+    pub longest_streak: usize,
+    /// Weekday with the most late days and how many there were
+    pub worst_weekday: Option<(Weekday, usize)>,
+    /// Documents of the period, oldest first
+    pub days: Vec<Day>,
+}
+
+/// A voter for display: their stored profile if known, otherwise just the id
+#[derive(Debug, Clone)]
+pub enum Voter {
+    Profile(UserProfile),
+    Id(i64),
+}
+
+impl Voter {
+    fn display_name(&self) -> String {
+        match self {
+            Voter::Profile(profile) => profile.display_name(),
+            Voter::Id(user_id) => user_id.to_string(),
+        }
+    }
+}
+
+/// Computes the digest for the last complete `period` before `today`
+pub async fn build_digest(
+    database_service: &DatabaseService,
+    period: DigestPeriod,
+    today: NaiveDate,
+) -> Result<Digest, StoreError> {
+    let (start, end) = period.last_complete(today);
+    let (previous_start, previous_end) = period.previous(start);
+
+    let days = database_service
+        .get_days_in_range(Day::start_of(start), Day::start_of(end))
+        .await?;
+    let previous_days = database_service
+        .get_days_in_range(Day::start_of(previous_start), Day::start_of(previous_end))
+        .await?;
+
+    let mut votes_per_user: HashMap<i64, usize> = HashMap::new();
+    for day in &days {
+        for &user_id in day.votes_yes.iter().chain(&day.votes_no) {
+            *votes_per_user.entry(user_id).or_default() += 1;
+        }
+    }
+    let mut ranking: Vec<(i64, usize)> = votes_per_user.into_iter().collect();
+    ranking.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    ranking.truncate(TOP_VOTERS);
+
+    let top_ids: Vec<i64> = ranking.iter().map(|(user_id, _)| *user_id).collect();
+    let profiles = database_service.get_user_profiles(&top_ids).await?;
+    let top_voters = ranking
+        .into_iter()
+        .map(|(user_id, votes)| {
+            let voter = profiles
+                .iter()
+                .find(|profile| profile.user_id == user_id)
+                .cloned()
+                .map(Voter::Profile)
+                .unwrap_or(Voter::Id(user_id));
+            (voter, votes)
+        })
+        .collect();
+
+    Ok(Digest {
+        period,
+        start,
+        end,
+        tracked_days: days.len(),
+        late_days: days.iter().filter(|day| day.is_late()).count(),
+        previous_late_days: previous_days.iter().filter(|day| day.is_late()).count(),
+        top_voters,
+        longest_streak: longest_streak(&days),
+        worst_weekday: worst_weekday(&days),
+        days,
+    })
+}
+
+fn longest_streak(days: &[Day]) -> usize {
+    let mut longest = 0;
+    let mut current = 0;
+    for day in days {
+        if day.is_late() {
+            current += 1;
+            longest = longest.max(current);
+        } else {
+            current = 0;
+        }
+    }
+    longest
+}
+
+fn worst_weekday(days: &[Day]) -> Option<(Weekday, usize)> {
+    let mut per_weekday = [0usize; 7];
+    for day in days.iter().filter(|day| day.is_late()) {
+        per_weekday[day.naive_date().weekday().num_days_from_monday() as usize] += 1;
+    }
+
+    // Ties go to the earlier weekday
+    let (index, &count) = per_weekday
+        .iter()
+        .enumerate()
+        .rev()
+        .max_by_key(|(_, &count)| count)?;
+    if count == 0 {
+        return None;
+    }
+    let weekday = Weekday::try_from(index as u8).ok()?;
+    Some((weekday, count))
+}
+
+/// Sends the digest text followed by the calendar heatmap of its days, the same whether
+/// it was scheduled or asked for with `/digest`. A chart that fails to render is logged
+/// and left out
+pub async fn send_digest(
+    bot: &Bot,
+    chat_id: ChatId,
+    lang: Lang,
+    digest: &Digest,
+    target_name: &str,
+) -> Result<(), RequestError> {
+    bot.send_message(chat_id, render_digest(lang, digest, target_name)).await?;
+
+    match render_calendar_heatmap(&digest.days, lang) {
+        Ok(png) => {
+            bot.send_photo(chat_id, InputFile::memory(png).file_name("digest.png")).await?;
+        }
+        Err(ChartError::NoData) => {}
+        Err(e) => log::error!("Failed to render the digest chart: {}", e),
+    }
+    Ok(())
+}
+
+/// Renders the digest as a plain-text message
+pub fn render_digest(lang: Lang, digest: &Digest, target_name: &str) -> String {
+    let last_day = digest.end.pred_opt().unwrap_or(digest.end);
+    let title_key = match digest.period {
+        DigestPeriod::Week => "digest_title_week",
+        DigestPeriod::Month => "digest_title_month",
+    };
+    let mut lines = vec![tr_args(
+        lang,
+        title_key,
+        &[
+            ("from", &digest.start.format("%d.%m.%Y")),
+            ("to", &last_day.format("%d.%m.%Y")),
+        ],
+    )];
+
+    if digest.tracked_days == 0 {
+        lines.push(tr(lang, "digest_no_data"));
+        return lines.join("\n\n");
+    }
+
+    lines.push(tr_args(
+        lang,
+        "digest_late_days",
+        &[
+            ("target", &target_name),
+            ("late_days", &tr_plural(lang, "days", digest.late_days as u64, &[])),
+            ("tracked", &digest.tracked_days),
+        ],
+    ));
+
+    let difference = digest.late_days as i64 - digest.previous_late_days as i64;
+    let comparison_key = match difference {
+        d if d > 0 => "digest_compare_more",
+        d if d < 0 => "digest_compare_less",
+        _ => "digest_compare_same",
+    };
+    lines.push(tr_plural(
+        lang,
+        comparison_key,
+        difference.unsigned_abs(),
+        &[("previous", &digest.previous_late_days)],
+    ));
+
+    if digest.longest_streak > 0 {
+        lines.push(tr_args(
+            lang,
+            "digest_streak",
+            &[("streak", &tr_plural(lang, "days", digest.longest_streak as u64, &[]))],
+        ));
+    }
+
+    if let Some((weekday, count)) = digest.worst_weekday {
+        let weekday_name = tr(lang, &format!("weekday_{}", weekday.number_from_monday()));
+        lines.push(tr_args(
+            lang,
+            "digest_worst_weekday",
+            &[
+                ("weekday", &weekday_name),
+                ("late_days", &tr_plural(lang, "days", count as u64, &[])),
+            ],
+        ));
+    }
+
+    if !digest.top_voters.is_empty() {
+        let mut voters = vec![tr(lang, "digest_top_voters")];
+        for (place, (voter, votes)) in digest.top_voters.iter().enumerate() {
+            voters.push(format!(
+                "{}. {} — {}",
+                place + 1,
+                voter.display_name(),
+                tr_plural(lang, "votes", *votes as u64, &[])
+            ));
+        }
+        lines.push(voters.join("\n"));
+    }
+
+    lines.join("\n\n")
+}
@@ -1,97 +1,83 @@
-use teloxide::{
-    prelude::*,
-    types::{CallbackQuery, InlineKeyboardButton, InlineKeyboardMarkup},
-    RequestError,
-};
+use std::sync::Arc;
+
+use teloxide::{prelude::*, types::CallbackQuery, RequestError};
 
-use crate::database_actions::DatabaseService;
+use crate::config::BotConfig;
+use crate::database_actions::{day::Day, DatabaseService, VoteReceipt};
+use crate::handlers::common::{
+    store_error_message, today_stats_message, vote_keyboard, vote_registered_message,
+};
+use crate::handlers::voting::{record_vote, Voter};
+use crate::localization::{resolve_lang, tr, Lang};
 
 pub async fn handle_callback(
     bot: Bot,
     q: CallbackQuery,
     database_service: DatabaseService,
+    bot_config: Arc<BotConfig>,
 ) -> Result<(), RequestError> {
+    // Buttons on messages sent via inline mode come without `message`, only with `inline_message_id`
+    let chat_id = q.message.as_ref().map(|m| m.chat().id.0);
+    let lang = resolve_lang(&database_service, chat_id, Some(&q.from), bot_config.default_lang()).await;
+
+    // Votes are journaled while the database is down; everything else needs it
+    let is_vote = matches!(q.data.as_deref(), Some("late") | Some("unlate"));
+    if !database_service.is_available() && !is_vote {
+        bot.answer_callback_query(q.id)
+            .text(tr(lang, "store_degraded"))
+            .show_alert(true)
+            .await?;
+        return Ok(());
+    }
+
     if let Some(data) = q.data {
         match data.as_str() {
             "late" | "unlate" => {
-                let user_id = q.from.id.0 as i64;
                 let is_late = data == "late";
 
-                match database_service.vote(user_id, is_late).await {
-                    Ok(_) => {
-                        let vote_type = if is_late {
-                            "за опоздание"
-                        } else {
-                            "против опоздания"
-                        };
+                match record_vote(&bot, &database_service, &bot_config, Voter::User(&q.from), Day::today_start(), is_late).await {
+                    Ok(receipt) => {
                         bot.answer_callback_query(q.id)
-                            .text(format!(
-                                "✅ Ваш голос {} успешно зарегистрирован!",
-                                vote_type
-                            ))
+                            .text(vote_registered_message(lang, is_late, receipt))
                             .await?;
+
+                        // Inline cards are shared with people who can't run /stats there,
+                        // so keep the tally on the card itself up to date
+                        if let (Some(inline_message_id), VoteReceipt::Recorded) = (q.inline_message_id, receipt) {
+                            refresh_inline_card(&bot, &database_service, &inline_message_id, bot_config.default_lang()).await;
+                        }
                     }
                     Err(e) => {
                         log::error!("Ошибка при голосовании: {}", e);
                         bot.answer_callback_query(q.id)
-                            .text("❌ Произошла ошибка при регистрации голоса. Пожалуйста, попробуйте позже.")
+                            .text(store_error_message(lang, "vote_error", &e))
                             .await?;
                     }
                 }
             }
             "stats" => {
-                if let Ok(today_document) = database_service.check_today_document().await {
-                    let user_id = q.from.id.0 as i64;
-                    let user_vote = if today_document.votes_yes.contains(&user_id) {
-                        "✅ Вы сегодня голосовали ЗА опоздание"
-                    } else if today_document.votes_no.contains(&user_id) {
-                        "❌ Вы сегодня голосовали ПРОТИВ опоздания"
-                    } else {
-                        "⚠️ Вы сегодня еще не голосовали"
-                    };
-
-                    let votes_yes = today_document.votes_yes.len();
-                    let votes_no = today_document.votes_no.len();
-
-                    let result_position = if votes_yes > votes_no {
-                        "🟢 Сейчас побеждает позиция: ОПОЗДАЛ"
-                    } else if votes_no > votes_yes {
-                        "🔴 Сейчас побеждает позиция: НЕ ОПОЗДАЛ"
-                    } else {
-                        "🟡 Сейчас ничья в голосовании"
-                    };
-
-                    let stats_message = format!(
-                        "📊 Статистика за сегодня:\n\n\
-                        За опоздание: {} голосов\n\
-                        Против опоздания: {} голосов\n\n\
-                        Всего проголосовало: {} человек\n\
-                        {}\n\n\
-                        {}",
-                        votes_yes,
-                        votes_no,
-                        votes_yes + votes_no,
-                        result_position,
-                        user_vote
-                    );
+                match database_service.check_today_document().await {
+                    Ok(today_document) => {
+                        let user_id = q.from.id.0 as i64;
+                        let stats_message = today_stats_message(lang, &today_document, Some(user_id));
 
-                    let keyboard = InlineKeyboardMarkup::new(vec![vec![
-                        InlineKeyboardButton::callback("✅ Опоздал", "late"),
-                        InlineKeyboardButton::callback("❌ Не опоздал", "unlate"),
-                    ]]);
+                        bot.answer_callback_query(q.id).await?;
 
-                    bot.answer_callback_query(q.id).await?;
-
-                    if let Some(message) = q.message {
-                        let chat = message.chat();
-                        bot.send_message(chat.id, stats_message)
-                            .reply_markup(keyboard)
+                        if let Some(message) = q.message {
+                            let chat = message.chat();
+                            bot.send_message(chat.id, stats_message)
+                                .reply_markup(vote_keyboard(lang))
+                                .await?;
+                        } else if let Some(inline_message_id) = q.inline_message_id {
+                            refresh_inline_card(&bot, &database_service, &inline_message_id, bot_config.default_lang()).await;
+                        }
+                    }
+                    Err(e) => {
+                        log::error!("Ошибка при получении статистики: {}", e);
+                        bot.answer_callback_query(q.id)
+                            .text(store_error_message(lang, "stats_error", &e))
                             .await?;
                     }
-                } else {
-                    bot.answer_callback_query(q.id)
-                        .text("❌ Произошла ошибка при получении статистики. Пожалуйста, попробуйте позже.")
-                        .await?;
                 }
             }
             _ => {}
@@ -99,3 +85,36 @@ pub async fn handle_callback(
     }
     Ok(())
 }
+
+/// Replaces an inline message with today's tally and the voting buttons.
+///
+/// The card is shared by the whole chat it was posted in, which Telegram doesn't reveal, so
+/// it is rendered in the default language rather than in the clicking user's.
+///
+/// Failures are only logged: Telegram rejects edits that don't change the text, which
+/// happens whenever a vote doesn't move the tally.
+async fn refresh_inline_card(
+    bot: &Bot,
+    database_service: &DatabaseService,
+    inline_message_id: &str,
+    lang: Lang,
+) {
+    let today_document = match database_service.check_today_document().await {
+        Ok(today_document) => today_document,
+        Err(e) => {
+            log::error!("Ошибка при получении статистики для inline-сообщения: {}", e);
+            return;
+        }
+    };
+
+    let result = bot
+        .edit_message_text_inline(
+            inline_message_id,
+            today_stats_message(lang, &today_document, None),
+        )
+        .reply_markup(vote_keyboard(lang))
+        .await;
+    if let Err(e) = result {
+        log::debug!("Inline message {} was not updated: {}", inline_message_id, e);
+    }
+}
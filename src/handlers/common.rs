@@ -0,0 +1,107 @@
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+use crate::database_actions::{day::Day, StoreError, VoteReceipt};
+use crate::localization::{tr, tr_args, tr_plural, Lang};
+
+/// Keyboard with the two voting buttons
+pub fn vote_keyboard(lang: Lang) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(vec![vec![
+        InlineKeyboardButton::callback(tr(lang, "button_late"), "late"),
+        InlineKeyboardButton::callback(tr(lang, "button_unlate"), "unlate"),
+    ]])
+}
+
+/// Keyboard with the voting buttons and a stats button, shown by `/start`
+pub fn start_keyboard(lang: Lang) -> InlineKeyboardMarkup {
+    vote_keyboard(lang).append_row(vec![stats_button(lang)])
+}
+
+/// Keyboard with only the stats button, used when voting happens in a native poll
+pub fn stats_keyboard(lang: Lang) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(vec![vec![stats_button(lang)]])
+}
+
+fn stats_button(lang: Lang) -> InlineKeyboardButton {
+    InlineKeyboardButton::callback(tr(lang, "button_stats"), "stats")
+}
+
+/// Confirmation shown after a vote is recorded or queued
+pub fn vote_registered_message(lang: Lang, is_late: bool, receipt: VoteReceipt) -> String {
+    let vote_type = if is_late {
+        tr(lang, "vote_type_late")
+    } else {
+        tr(lang, "vote_type_unlate")
+    };
+    let key = match receipt {
+        VoteReceipt::Recorded => "vote_registered",
+        VoteReceipt::Queued => "vote_queued",
+    };
+    tr_args(lang, key, &[("vote_type", &vote_type)])
+}
+
+/// Builds today's stats card.
+///
+/// With `user_id` the card also says how that user voted; without it (e.g. for inline
+/// messages shared into other chats) only the tally is shown.
+pub fn today_stats_message(lang: Lang, today_document: &Day, user_id: Option<i64>) -> String {
+    let votes_yes = today_document.votes_yes.len() as u64;
+    let votes_no = today_document.votes_no.len() as u64;
+
+    let result_position = if votes_yes > votes_no {
+        tr(lang, "position_late")
+    } else if votes_no > votes_yes {
+        tr(lang, "position_unlate")
+    } else {
+        tr(lang, "position_tie")
+    };
+
+    let stats_message = tr_args(
+        lang,
+        "stats_today",
+        &[
+            ("votes_yes", &tr_plural(lang, "votes", votes_yes, &[])),
+            ("votes_no", &tr_plural(lang, "votes", votes_no, &[])),
+            ("voters", &tr_plural(lang, "people", votes_yes + votes_no, &[])),
+            ("position", &result_position),
+        ],
+    );
+
+    let Some(user_id) = user_id else {
+        return stats_message;
+    };
+
+    let user_vote = if today_document.votes_yes.contains(&user_id) {
+        tr(lang, "user_voted_late")
+    } else if today_document.votes_no.contains(&user_id) {
+        tr(lang, "user_voted_unlate")
+    } else {
+        tr(lang, "user_not_voted")
+    };
+
+    format!("{}\n\n{}", stats_message, user_vote)
+}
+
+/// Builds the all-time summary card
+pub fn summary_message(lang: Lang, target_name: &str, total_late_days: u64, total_days: u64) -> String {
+    tr_args(
+        lang,
+        "stats_summary",
+        &[
+            ("target", &target_name),
+            ("late_days", &tr_plural(lang, "days", total_late_days, &[])),
+            ("total_days", &total_days),
+        ],
+    )
+}
+
+/// Failure message for a database error: what failed, then why
+pub fn store_error_message(lang: Lang, context_key: &str, error: &StoreError) -> String {
+    let reason_key = match error {
+        StoreError::Connection(_) => "store_unavailable",
+        StoreError::Rejected(_) => "store_rejected",
+        StoreError::NotFound(_) => "store_not_found",
+        StoreError::Conflict(_) => "store_conflict",
+        StoreError::Serialization(_) => "store_malformed",
+    };
+    format!("{}\n{}", tr(lang, context_key), tr(lang, reason_key))
+}
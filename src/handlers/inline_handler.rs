@@ -0,0 +1,101 @@
+use std::sync::Arc;
+
+use teloxide::{
+    prelude::*,
+    types::{
+        InlineQuery, InlineQueryResult, InlineQueryResultArticle, InputMessageContent,
+        InputMessageContentText,
+    },
+    RequestError,
+};
+
+use crate::config::BotConfig;
+use crate::database_actions::DatabaseService;
+use crate::handlers::common::{start_keyboard, summary_message, today_stats_message, vote_keyboard};
+use crate::localization::{tr, tr_args, user_lang};
+
+/// Answers `@bot` inline queries with today's status, a vote card and the all-time summary.
+///
+/// The cards are built from the same data as `/stats`. Buttons on the resulting messages
+/// are handled by `handle_callback`, which knows how to edit inline messages. The titles
+/// are in the asking user's language, while the messages, read by the whole chat and
+/// re-rendered on every click, are in the default language.
+pub async fn handle_inline_query(
+    bot: Bot,
+    q: InlineQuery,
+    database_service: DatabaseService,
+    bot_config: Arc<BotConfig>,
+) -> Result<(), RequestError> {
+    let lang = user_lang(Some(&q.from), bot_config.default_lang());
+    let card_lang = bot_config.default_lang();
+    let mut results = Vec::new();
+
+    // Without the database there is nothing to show; Telegram displays no results
+    if !database_service.is_available() {
+        log::debug!("Database unavailable, leaving inline query {} unanswered", q.id);
+        return Ok(());
+    }
+
+    match database_service.check_today_document().await {
+        Ok(today_document) => {
+            let today_card = InlineQueryResultArticle::new(
+                "today",
+                tr(lang, "inline_title_today"),
+                text_content(today_stats_message(card_lang, &today_document, None)),
+            )
+            .description(tr(lang, "inline_description_today"))
+            .reply_markup(vote_keyboard(card_lang));
+            results.push(InlineQueryResult::Article(today_card));
+        }
+        Err(e) => log::error!("Ошибка при получении статистики для inline-запроса: {}", e),
+    }
+
+    let vote_card = InlineQueryResultArticle::new(
+        "vote",
+        tr(lang, "inline_title_vote"),
+        text_content(tr_args(card_lang, "vote_card", &[("target", &bot_config.target_name)])),
+    )
+    .description(tr(lang, "inline_description_vote"))
+    .reply_markup(start_keyboard(card_lang));
+    results.push(InlineQueryResult::Article(vote_card));
+
+    let totals = (
+        database_service.get_total_late_days().await,
+        database_service.get_total_days().await,
+    );
+    match totals {
+        (Ok(total_late_days), Ok(total_days)) => {
+            let summary_card = InlineQueryResultArticle::new(
+                "summary",
+                tr(lang, "inline_title_summary"),
+                text_content(summary_message(
+                    card_lang,
+                    &bot_config.target_name,
+                    total_late_days as u64,
+                    total_days,
+                )),
+            )
+            .description(tr_args(
+                lang,
+                "inline_description_summary",
+                &[("target", &bot_config.target_name)],
+            ));
+            results.push(InlineQueryResult::Article(summary_card));
+        }
+        (Err(e), _) | (_, Err(e)) => {
+            log::error!("Ошибка при получении общей статистики для inline-запроса: {}", e)
+        }
+    }
+
+    // The cards reflect live data and their titles are in the asking user's language, so
+    // nothing is cached
+    bot.answer_inline_query(q.id, results)
+        .cache_time(0)
+        .is_personal(true)
+        .await?;
+    Ok(())
+}
+
+fn text_content(text: String) -> InputMessageContent {
+    InputMessageContent::Text(InputMessageContentText::new(text))
+}
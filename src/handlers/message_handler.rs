@@ -1,168 +1,363 @@
-use teloxide::{
-    prelude::*,
-    types::{InlineKeyboardButton, InlineKeyboardMarkup, MessageEntityKind},
-    RequestError,
+use std::sync::Arc;
+
+use chrono::{Days, FixedOffset, Months, NaiveDate, Utc};
+use teloxide::{net::Download, prelude::*, types::InputFile, RequestError};
+
+use crate::charts::{render_calendar_heatmap, render_day_votes, render_monthly_bars, ChartError};
+use crate::config::{BotConfig, VoteMode};
+use crate::database_actions::{day::Day, DatabaseService};
+use crate::digest::{build_digest, send_digest, DigestPeriod};
+use crate::handlers::common::{
+    start_keyboard, stats_keyboard, store_error_message, today_stats_message, vote_keyboard,
+    vote_registered_message,
+};
+use crate::handlers::poll_handler::post_today_poll;
+use crate::history::{
+    export::{load_records, serialize_records},
+    import::{apply_import, parse_records, plan_import, render_invalid, render_plan},
+    parse_date, HistoryFormat,
 };
+use crate::handlers::voting::{record_vote, Voter};
+use crate::localization::{resolve_lang, tr, tr_args, user_lang, Lang};
 
-use mongodb::bson::DateTime;
-use crate::database_actions::DatabaseService;
+/// Largest file `/import` accepts
+const MAX_IMPORT_BYTES: u32 = 5 * 1024 * 1024;
 
 pub async fn message_handler(
     bot: Bot,
     msg: Message,
-    target_name: String,
-    ping_user: String,
     database_service: DatabaseService,
-    notification_chat_id: i64,
+    bot_config: Arc<BotConfig>,
 ) -> Result<(), RequestError> {
-    match msg.text() {
-        Some("/start") => {
-            let keyboard = InlineKeyboardMarkup::new(vec![
-                vec![
-                    InlineKeyboardButton::callback("✅ Опоздал", "late"),
-                    InlineKeyboardButton::callback("❌ Не опоздал", "unlate"),
-                ],
-                vec![InlineKeyboardButton::callback("📊 Статистика", "stats")],
-            ]);
+    let default_lang = bot_config.default_lang();
+    let lang = resolve_lang(
+        &database_service,
+        Some(msg.chat.id.0),
+        msg.from.as_ref(),
+        default_lang,
+    )
+    .await;
+
+    // Файлы для импорта приходят с командой в подписи
+    let text = msg.text().or_else(|| msg.document().and(msg.caption()));
+
+    // Пока база недоступна, голоса копятся в журнале, а остальные команды, которым
+    // нужна база, не работают
+    let works_offline = matches!(text, Some("/late") | Some("/unlate") | Some("/get_chat_id") | Some("/my_id"));
+    if !database_service.is_available() && !works_offline {
+        bot.send_message(msg.chat.id, tr(lang, "store_degraded")).await?;
+        return Ok(());
+    }
 
+    match text {
+        Some("/start") => {
+            let keyboard = match bot_config.vote_mode {
+                VoteMode::Buttons => start_keyboard(lang),
+                VoteMode::Poll => stats_keyboard(lang),
+            };
             bot.send_message(
                 msg.chat.id,
-                format!(
-                    "👋 Добро пожаловать в бот учета опозданий!\n\n\
-                🕒 Здесь вы можете голосовать, опоздал ли сегодня {}.\n\n\
-                Команды:\n\
-                /late - голосовать за опоздание\n\
-                /unlate - голосовать против опоздания\n\
-                /stats - посмотреть статистику\n\
-                /get_chat_id - получить ID текущего чата\n\
-                /get_user_id @username - информация о получении ID пользователя\n\
-                /my_id - получить свой ID\n\n\
-                ⚠️ Голосовать можно только один раз в день!",
-                    target_name
-                ),
+                tr_args(lang, "welcome", &[("target", &bot_config.target_name)]),
             )
             .reply_markup(keyboard)
             .await?;
+
+            if bot_config.vote_mode == VoteMode::Poll {
+                post_today_poll(&bot, &database_service, &bot_config, msg.chat.id, lang).await?;
+            }
+        }
+        Some("/poll") => {
+            post_today_poll(&bot, &database_service, &bot_config, msg.chat.id, lang).await?;
         }
         Some("/late") | Some("/unlate") => {
-            let user_id = msg.from.as_ref().unwrap().id;
-            let _username = msg
-                .from
-                .as_ref()
-                .unwrap()
-                .username
-                .clone()
-                .unwrap_or_else(|| "anonymous".to_string());
+            // Сообщения от имени каналов приходят без отправителя
+            let Some(user) = msg.from.as_ref() else {
+                return Ok(());
+            };
             let is_late = msg.text() == Some("/late");
 
-            let _ = database_service.check_today_document().await;
-
-            match database_service.vote(user_id.0 as i64, is_late).await {
-                Ok(_) => {
-                    let vote_type = if is_late {
-                        "за опоздание"
-                    } else {
-                        "против опоздания"
-                    };
-                    bot.send_message(
-                        msg.chat.id,
-                        format!("✅ Ваш голос {} успешно зарегистрирован!", vote_type),
-                    )
-                    .await?;
-
-                    if is_late {
-                        if let Ok(total_late_days) = database_service.check_today_document().await {
-                            if total_late_days.votes_yes.len() % 5 == 0 && notification_chat_id != 0
-                            {
-                                bot.send_message(
-                                    ChatId(notification_chat_id),
-                                    format!("🎉 {} Человек сообщили, что {}({}) опоздал! 🎉🎉🎉🎉🎉 Давайте его поздравим! 🎉🎉🎉🎉🎉 ", total_late_days.votes_yes.len(),  target_name,ping_user,)
-                                ).await?;
-                            }
-                        }
-                    }
+            match record_vote(&bot, &database_service, &bot_config, Voter::User(user), Day::today_start(), is_late).await {
+                Ok(receipt) => {
+                    bot.send_message(msg.chat.id, vote_registered_message(lang, is_late, receipt))
+                        .await?;
                 }
                 Err(e) => {
                     log::error!("Ошибка при голосовании: {}", e);
-                    bot.send_message(
-                        msg.chat.id,
-                        "❌ Произошла ошибка при регистрации голоса. Пожалуйста, попробуйте позже.",
-                    )
-                    .await?;
+                    bot.send_message(msg.chat.id, store_error_message(lang, "vote_error", &e)).await?;
                 }
             }
         }
         Some("/stats") => {
-            if let Ok(today_document) = database_service.check_today_document().await {
-                let user_id = msg.from.as_ref().unwrap().id.0 as i64;
-                let user_vote = if today_document.votes_yes.contains(&user_id) {
-                    "✅ Вы сегодня голосовали ЗА опоздание"
-                } else if today_document.votes_no.contains(&user_id) {
-                    "❌ Вы сегодня голосовали ПРОТИВ опоздания"
-                } else {
-                    "⚠️ Вы сегодня еще не голосовали"
-                };
-
-                let votes_yes = today_document.votes_yes.len();
-                let votes_no = today_document.votes_no.len();
-
-                let result_position = if votes_yes > votes_no {
-                    "🟢 Сейчас побеждает позиция: ОПОЗДАЛ"
-                } else if votes_no > votes_yes {
-                    "🔴 Сейчас побеждает позиция: НЕ ОПОЗДАЛ"
-                } else {
-                    "🟡 Сейчас ничья в голосовании"
-                };
-
-                let stats_message = format!(
-                    "📊 Статистика за сегодня:\n\n\
-                    За опоздание: {} голосов\n\
-                    Против опоздания: {} голосов\n\n\
-                    Всего проголосовало: {} человек\n\
-                    {}\n\n\
-                    {}",
-                    votes_yes,
-                    votes_no,
-                    votes_yes + votes_no,
-                    result_position,
-                    user_vote
-                );
-
-                let keyboard = InlineKeyboardMarkup::new(vec![vec![
-                    InlineKeyboardButton::callback("✅ Опоздал", "late"),
-                    InlineKeyboardButton::callback("❌ Не опоздал", "unlate"),
-                ]]);
-
-                bot.send_message(msg.chat.id, stats_message)
-                    .reply_markup(keyboard)
-                    .await?;
-            } else {
-                bot.send_message(
-                    msg.chat.id,
-                    "Произошла ошибка при получении статистики. Пожалуйста, попробуйте позже.",
-                )
+            match database_service.check_today_document().await {
+                Ok(today_document) => {
+                    let user_id = msg.from.as_ref().map(|user| user.id.0 as i64);
+
+                    bot.send_message(msg.chat.id, today_stats_message(lang, &today_document, user_id))
+                        .reply_markup(vote_keyboard(lang))
+                        .await?;
+                }
+                Err(e) => {
+                    log::error!("Ошибка при получении статистики: {}", e);
+                    bot.send_message(msg.chat.id, store_error_message(lang, "stats_error", &e)).await?;
+                }
+            }
+        }
+        Some(text) if text == "/digest" || text.starts_with("/digest ") => {
+            let argument = text.trim_start_matches("/digest").trim();
+            let Some(period) = DigestPeriod::parse(argument) else {
+                bot.send_message(msg.chat.id, tr(lang, "digest_usage")).await?;
+                return Ok(());
+            };
+
+            match build_digest(&database_service, period, Utc::now().date_naive()).await {
+                Ok(digest) => send_digest(&bot, msg.chat.id, lang, &digest, &bot_config.target_name).await?,
+                Err(e) => {
+                    log::error!("Ошибка при построении сводки: {}", e);
+                    bot.send_message(msg.chat.id, store_error_message(lang, "digest_error", &e)).await?;
+                }
+            }
+        }
+        Some(text) if text == "/chart" || text.starts_with("/chart ") => {
+            send_chart(&bot, &msg, &database_service, &bot_config, lang, text.trim_start_matches("/chart").trim())
                 .await?;
+        }
+        Some(text) if text == "/export" || text.starts_with("/export ") => {
+            let user_id = msg.from.as_ref().map(|user| user.id.0 as i64).unwrap_or_default();
+            if !bot_config.is_admin(user_id) {
+                bot.send_message(msg.chat.id, tr(lang, "admin_only")).await?;
+                return Ok(());
+            }
+            send_export(&bot, &msg, &database_service, lang, text.trim_start_matches("/export")).await?;
+        }
+        Some(text) if text == "/import" || text.starts_with("/import ") => {
+            let user_id = msg.from.as_ref().map(|user| user.id.0 as i64).unwrap_or_default();
+            if !bot_config.is_admin(user_id) {
+                bot.send_message(msg.chat.id, tr(lang, "admin_only")).await?;
+                return Ok(());
+            }
+            receive_import(&bot, &msg, &database_service, lang, text.trim_start_matches("/import").trim()).await?;
+        }
+        Some(text) if text == "/language" || text.starts_with("/language ") => {
+            let argument = text.trim_start_matches("/language").trim();
+            let result = match argument {
+                "auto" => database_service
+                    .set_chat_language(msg.chat.id.0, None)
+                    .await
+                    .map(|_| tr(user_lang(msg.from.as_ref(), default_lang), "language_auto")),
+                code => match Lang::from_code(code) {
+                    Some(new_lang) => database_service
+                        .set_chat_language(msg.chat.id.0, Some(new_lang.code()))
+                        .await
+                        .map(|_| tr(new_lang, "language_set")),
+                    None => Ok(tr(lang, "language_usage")),
+                },
+            };
+
+            match result {
+                Ok(reply) => {
+                    bot.send_message(msg.chat.id, reply).await?;
+                }
+                Err(e) => {
+                    log::error!("Ошибка при сохранении языка чата: {}", e);
+                    bot.send_message(msg.chat.id, store_error_message(lang, "language_error", &e)).await?;
+                }
             }
         }
         Some("/get_chat_id") => {
-            bot.send_message(msg.chat.id, format!("ID этого чата: {}", msg.chat.id))
-                .await?;
+            bot.send_message(
+                msg.chat.id,
+                tr_args(lang, "chat_id", &[("chat_id", &msg.chat.id)]),
+            )
+            .await?;
         }
         Some("/my_id") => {
             if let Some(user) = &msg.from {
                 bot.send_message(
                     msg.chat.id,
-                    format!("Ваш ID: {}", user.id.0)
-                ).await?;
+                    tr_args(lang, "my_id", &[("user_id", &user.id.0)]),
+                )
+                .await?;
             } else {
-                bot.send_message(msg.chat.id, "Не удалось определить ваш ID").await?;
+                bot.send_message(msg.chat.id, tr(lang, "my_id_unknown")).await?;
             }
         }
         _ => {
-            bot.send_message(
-                msg.chat.id,
-                "Используйте /start для информации, /late для голосования за опоздание, /unlate для голосования против, /stats для статистики за сегодня, /get_chat_id для получения ID чата, /my_id для получения своего ID"
-            ).await?;
+            bot.send_message(msg.chat.id, tr(lang, "usage")).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Handles `/chart [calendar|months|today]`
+async fn send_chart(
+    bot: &Bot,
+    msg: &Message,
+    database_service: &DatabaseService,
+    bot_config: &BotConfig,
+    lang: Lang,
+    kind: &str,
+) -> Result<(), RequestError> {
+    let today = Utc::now().date_naive();
+    let offset = bot_config.utc_offset().unwrap_or(FixedOffset::east_opt(0).unwrap());
+
+    let rendered = match kind {
+        "" | "calendar" => database_service
+            .get_days_in_range(Day::start_of(today - Months::new(12)), Day::start_of(today + Days::new(1)))
+            .await
+            .map(|days| render_calendar_heatmap(&days, lang)),
+        "months" => database_service
+            .get_days_in_range(Day::start_of(today - Months::new(24)), Day::start_of(today + Days::new(1)))
+            .await
+            .map(|days| render_monthly_bars(&days, lang)),
+        "today" => database_service
+            .check_today_document()
+            .await
+            .map(|day| render_day_votes(&day, lang, offset)),
+        _ => {
+            bot.send_message(msg.chat.id, tr(lang, "chart_usage")).await?;
+            return Ok(());
+        }
+    };
+
+    match rendered {
+        Ok(Ok(png)) => {
+            bot.send_photo(msg.chat.id, InputFile::memory(png).file_name("chart.png"))
+                .await?;
+        }
+        Ok(Err(ChartError::NoData)) => {
+            bot.send_message(msg.chat.id, tr(lang, "chart_no_data")).await?;
+        }
+        Ok(Err(e)) => {
+            log::error!("Ошибка при построении графика: {}", e);
+            bot.send_message(msg.chat.id, tr(lang, "chart_error")).await?;
+        }
+        Err(e) => {
+            log::error!("Ошибка при получении данных для графика: {}", e);
+            bot.send_message(msg.chat.id, store_error_message(lang, "chart_error", &e)).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Handles `/export [csv|json] [from] [to]`
+async fn send_export(
+    bot: &Bot,
+    msg: &Message,
+    database_service: &DatabaseService,
+    lang: Lang,
+    arguments: &str,
+) -> Result<(), RequestError> {
+    let mut arguments = arguments.split_whitespace().peekable();
+    let format = match arguments.peek().and_then(|value| HistoryFormat::parse(value)) {
+        Some(format) => {
+            arguments.next();
+            format
+        }
+        None => HistoryFormat::Csv,
+    };
+    let dates: Option<Vec<NaiveDate>> = arguments.map(parse_date).collect();
+    let (from, to) = match dates.as_deref() {
+        Some([]) => (None, None),
+        Some([from]) => (Some(*from), None),
+        Some([from, to]) if from <= to => (Some(*from), Some(*to)),
+        _ => {
+            bot.send_message(msg.chat.id, tr(lang, "export_usage")).await?;
+            return Ok(());
+        }
+    };
+
+    let records = match load_records(database_service, from, to).await {
+        Ok(records) => records,
+        Err(e) => {
+            log::error!("Ошибка при загрузке истории для экспорта: {}", e);
+            bot.send_message(msg.chat.id, store_error_message(lang, "export_error", &e)).await?;
+            return Ok(());
+        }
+    };
+
+    match serialize_records(&records, format) {
+        Ok(bytes) => {
+            let file_name = format!(
+                "latebot-history-{}.{}",
+                Utc::now().format("%Y-%m-%d"),
+                format.extension()
+            );
+            bot.send_document(msg.chat.id, InputFile::memory(bytes).file_name(file_name))
+                .await?;
+        }
+        Err(e) => {
+            log::error!("Ошибка при экспорте истории: {}", e);
+            bot.send_message(msg.chat.id, tr(lang, "export_error")).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Handles `/import [commit]` sent as the caption of a file or as a reply to one.
+///
+/// Without `commit` only the difference with the stored history is shown.
+async fn receive_import(
+    bot: &Bot,
+    msg: &Message,
+    database_service: &DatabaseService,
+    lang: Lang,
+    argument: &str,
+) -> Result<(), RequestError> {
+    let commit = match argument {
+        "" => false,
+        "commit" => true,
+        _ => {
+            bot.send_message(msg.chat.id, tr(lang, "import_usage")).await?;
+            return Ok(());
+        }
+    };
+    let Some(document) = msg
+        .document()
+        .or_else(|| msg.reply_to_message().and_then(|reply| reply.document()))
+    else {
+        bot.send_message(msg.chat.id, tr(lang, "import_usage")).await?;
+        return Ok(());
+    };
+    if document.file.size > MAX_IMPORT_BYTES {
+        bot.send_message(msg.chat.id, tr(lang, "import_too_large")).await?;
+        return Ok(());
+    }
+
+    let file = bot.get_file(document.file.id.clone()).await?;
+    let mut contents = Vec::new();
+    if let Err(e) = bot.download_file(&file.path, &mut contents).await {
+        log::error!("Ошибка при загрузке файла импорта: {}", e);
+        bot.send_message(msg.chat.id, tr(lang, "import_error")).await?;
+        return Ok(());
+    }
+
+    let format = HistoryFormat::detect(document.file_name.as_deref(), &contents);
+    let records = match parse_records(&contents, format, Utc::now().date_naive()) {
+        Ok(records) => records,
+        Err(errors) => {
+            bot.send_message(msg.chat.id, render_invalid(lang, &errors)).await?;
+            return Ok(());
+        }
+    };
+
+    let imported = match plan_import(database_service, &records).await {
+        Ok(plan) if commit => apply_import(database_service, &plan).await.map(|_| plan),
+        planned => planned,
+    };
+    match imported {
+        Ok(plan) => {
+            let mut report = render_plan(lang, &plan, commit);
+            if !commit && !plan.changes.is_empty() {
+                report = format!("{}\n\n{}", report, tr(lang, "import_commit_hint"));
+            }
+            if commit {
+                log::info!("Импортировано дней: {}, голосов: {}", plan.changes.len(), plan.added_votes());
+            }
+            bot.send_message(msg.chat.id, report).await?;
+        }
+        Err(e) => {
+            log::error!("Ошибка при импорте истории: {}", e);
+            bot.send_message(msg.chat.id, store_error_message(lang, "import_error", &e)).await?;
         }
     }
     Ok(())
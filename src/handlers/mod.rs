@@ -1,5 +1,11 @@
 pub mod message_handler;
 pub mod callback_handler;
+pub mod common;
+pub mod inline_handler;
+pub mod poll_handler;
+pub mod voting;
 
 pub use message_handler::message_handler;
 pub use callback_handler::handle_callback;
+pub use inline_handler::handle_inline_query;
+pub use poll_handler::handle_poll_answer;
@@ -0,0 +1,112 @@
+use std::sync::Arc;
+
+use teloxide::{
+    prelude::*,
+    types::{InputPollOption, PollAnswer},
+    RequestError,
+};
+
+use crate::config::BotConfig;
+use crate::database_actions::{day::PollRef, DatabaseService, StoreError};
+use crate::handlers::common::store_error_message;
+use crate::handlers::voting::{record_vote, Voter};
+use crate::localization::{tr, tr_args, Lang};
+
+/// Index of the "late" option in the poll; the other option means "on time"
+const LATE_OPTION: u8 = 0;
+
+/// Posts today's non-anonymous poll to `chat_id` and remembers it so answers count as votes
+pub async fn post_today_poll(
+    bot: &Bot,
+    database_service: &DatabaseService,
+    bot_config: &BotConfig,
+    chat_id: ChatId,
+    lang: Lang,
+) -> Result<(), RequestError> {
+    let today_document = match database_service.check_today_document().await {
+        Ok(today_document) => today_document,
+        Err(e) => {
+            log::error!("Ошибка при создании документа дня для опроса: {}", e);
+            bot.send_message(chat_id, store_error_message(lang, "poll_error", &e)).await?;
+            return Ok(());
+        }
+    };
+
+    let message = bot
+        .send_poll(
+            chat_id,
+            tr_args(lang, "poll_question", &[("target", &bot_config.target_name)]),
+            [
+                InputPollOption::from(tr(lang, "button_late")),
+                InputPollOption::from(tr(lang, "button_unlate")),
+            ],
+        )
+        .is_anonymous(false)
+        .await?;
+
+    if let Some(poll) = message.poll() {
+        let poll_ref = PollRef {
+            poll_id: poll.id.clone(),
+            chat_id: chat_id.0,
+            message_id: message.id.0,
+        };
+        if let Err(e) = database_service.attach_poll(today_document.date, &poll_ref).await {
+            log::error!("Не удалось сохранить опрос {}: {}", poll_ref.poll_id, e);
+        }
+    }
+    Ok(())
+}
+
+/// Turns an answer to one of our polls into a vote for the day the poll was posted on
+pub async fn handle_poll_answer(
+    bot: Bot,
+    answer: PollAnswer,
+    database_service: DatabaseService,
+    bot_config: Arc<BotConfig>,
+) -> Result<(), RequestError> {
+    let Some(user) = answer.voter.user() else {
+        return Ok(());
+    };
+    let user_id = user.id.0 as i64;
+
+    // An empty answer means the user retracted their vote
+    let vote = answer.option_ids.first().map(|&option| option == LATE_OPTION);
+
+    // Without the database the poll can't be matched to its day, so the answer is
+    // journaled by poll id and matched on replay
+    if !database_service.is_available() {
+        queue_answer(&database_service, &answer.poll_id, user_id, vote).await;
+        return Ok(());
+    }
+
+    let day = match database_service.find_day_by_poll(&answer.poll_id).await {
+        Ok(Some(day)) => day,
+        Ok(None) => return Ok(()),
+        Err(e @ StoreError::Connection(_)) => {
+            log::warn!("Ошибка при поиске опроса {}, ответ отложен: {}", answer.poll_id, e);
+            database_service.mark_unavailable();
+            queue_answer(&database_service, &answer.poll_id, user_id, vote).await;
+            return Ok(());
+        }
+        Err(e) => {
+            log::error!("Ошибка при поиске опроса {}: {}", answer.poll_id, e);
+            return Ok(());
+        }
+    };
+
+    let result = match vote {
+        None => database_service.remove_vote(day.date, user_id).await,
+        Some(is_late) => record_vote(&bot, &database_service, &bot_config, Voter::User(user), day.date, is_late).await,
+    };
+
+    if let Err(e) = result {
+        log::error!("Ошибка при голосовании через опрос: {}", e);
+    }
+    Ok(())
+}
+
+async fn queue_answer(database_service: &DatabaseService, poll_id: &str, user_id: i64, vote: Option<bool>) {
+    if let Err(e) = database_service.queue_poll_answer(poll_id, user_id, vote).await {
+        log::error!("Не удалось сохранить ответ на опрос {}: {}", poll_id, e);
+    }
+}
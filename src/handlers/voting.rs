@@ -0,0 +1,67 @@
+use mongodb::bson::DateTime;
+use teloxide::{prelude::*, types::User};
+
+use crate::config::BotConfig;
+use crate::database_actions::{day::Day, user_profile::UserProfile, DatabaseService, StoreError, VoteReceipt};
+use crate::milestones::announce_milestones;
+
+/// Whose vote is recorded
+pub enum Voter<'a> {
+    /// A Telegram user voting for themselves
+    User(&'a User),
+    /// A user voted for by id, e.g. from the console
+    Id(i64),
+}
+
+/// Records a vote for the day starting at `date` and runs the milestone announcements.
+///
+/// Every entry point that accepts votes goes through here, so the announcements behave the
+/// same whether the vote came from a command, a button or the console. A failure to
+/// announce is logged and does not fail the vote itself. A vote queued while the database
+/// is down, or cast for a past day, gets no announcements.
+pub async fn record_vote(
+    bot: &Bot,
+    database_service: &DatabaseService,
+    bot_config: &BotConfig,
+    voter: Voter<'_>,
+    date: DateTime,
+    is_late: bool,
+) -> Result<VoteReceipt, StoreError> {
+    let user_id = match voter {
+        Voter::User(user) => user.id.0 as i64,
+        Voter::Id(user_id) => user_id,
+    };
+
+    // The vote creates the day's document if needed
+    if database_service.vote_on_day(date, user_id, is_late).await? == VoteReceipt::Queued {
+        return Ok(VoteReceipt::Queued);
+    }
+    if date != Day::today_start() {
+        return Ok(VoteReceipt::Recorded);
+    }
+
+    match database_service.check_today_document().await {
+        Ok(today_document) => {
+            if let Err(e) =
+                announce_milestones(bot, database_service, bot_config, &today_document, is_late).await
+            {
+                log::error!("Failed to post milestone announcement: {}", e);
+            }
+        }
+        Err(e) => log::error!("Failed to load today's document for milestones: {}", e),
+    }
+    // Names are only needed to mention voters in scheduled posts, so this is best effort
+    let Voter::User(user) = voter else {
+        return Ok(VoteReceipt::Recorded);
+    };
+    let profile = UserProfile {
+        user_id,
+        first_name: user.first_name.clone(),
+        last_name: user.last_name.clone(),
+        username: user.username.clone(),
+    };
+    if let Err(e) = database_service.remember_user(&profile).await {
+        log::warn!("Failed to remember user {}: {}", user_id, e);
+    }
+    Ok(VoteReceipt::Recorded)
+}
@@ -0,0 +1,10 @@
+use serde::{Deserialize, Serialize};
+
+/// The HTTP health endpoints
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct HealthConfig {
+    /// Address serving `/healthz` and `/readyz`, e.g. `0.0.0.0:8080`; empty disables them
+    #[serde(default)]
+    pub bind_address: String,
+}
@@ -0,0 +1,75 @@
+//! Liveness and readiness of the bot for docker-compose and orchestrators.
+//!
+//! `/healthz` answers as long as the process runs. `/readyz` also requires the database to
+//! be reachable and Telegram polling to work. Polling counts as failing from its last error
+//! until [`POLLING_RECOVERY`] passed without another one: the listener retries with a
+//! backoff of at most a minute, so an ongoing outage keeps reporting errors within that
+//! time, while a successful poll reports nothing.
+
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::future::BoxFuture;
+use teloxide::error_handlers::ErrorHandler;
+
+use crate::database_actions::DatabaseService;
+
+pub mod config;
+pub mod server;
+
+/// Longest retry delay of the polling listener plus its long-polling timeout, with margin
+const POLLING_RECOVERY: Duration = Duration::from_secs(90);
+
+/// State of Telegram polling, shared by the dispatcher and the health endpoints
+#[derive(Default)]
+pub struct PollingStatus {
+    running: AtomicBool,
+    last_error: Mutex<Option<Instant>>,
+}
+
+impl PollingStatus {
+    pub fn set_running(&self, running: bool) {
+        self.running.store(running, Ordering::Relaxed);
+    }
+
+    /// Why polling isn't working, or `None` if it is
+    pub fn problem(&self) -> Option<&'static str> {
+        if !self.running.load(Ordering::Relaxed) {
+            return Some("telegram polling is not running");
+        }
+        let last_error = *self.last_error.lock().unwrap_or_else(|e| e.into_inner());
+        match last_error {
+            Some(at) if at.elapsed() < POLLING_RECOVERY => Some("telegram polling is failing"),
+            _ => None,
+        }
+    }
+}
+
+/// Used as the dispatcher's update listener error handler, logging like teloxide's own
+impl<E: Debug + Send + 'static> ErrorHandler<E> for PollingStatus {
+    fn handle_error(self: Arc<Self>, error: E) -> BoxFuture<'static, ()> {
+        *self.last_error.lock().unwrap_or_else(|e| e.into_inner()) = Some(Instant::now());
+        log::error!("An error from the update listener: {:?}", error);
+        Box::pin(async {})
+    }
+}
+
+/// What the health endpoints look at
+pub struct HealthState {
+    pub database_service: DatabaseService,
+    pub polling: Arc<PollingStatus>,
+}
+
+impl HealthState {
+    /// Reasons the bot isn't ready; empty when it is
+    pub fn readiness_problems(&self) -> Vec<&'static str> {
+        let mut problems = Vec::new();
+        if !self.database_service.is_available() {
+            problems.push("database is unreachable");
+        }
+        problems.extend(self.polling.problem());
+        problems
+    }
+}
@@ -0,0 +1,107 @@
+//! A minimal HTTP/1.1 server for the two health endpoints, and the client side used by
+//! `latebot healthcheck`. Every response closes the connection.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+
+use super::HealthState;
+
+/// How long a client gets to send its request, and the healthcheck to get an answer
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest request head accepted
+const MAX_REQUEST: u64 = 8 * 1024;
+
+/// Starts serving `/healthz` and `/readyz` on `address`
+pub async fn start_health_server(state: Arc<HealthState>, address: &str) {
+    let listener = match TcpListener::bind(address).await {
+        Ok(listener) => listener,
+        Err(e) => return log::error!("Health endpoints are unavailable on {}: {}", address, e),
+    };
+    log::info!("Health endpoints available on http://{}/healthz and /readyz", address);
+
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let state = state.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = respond(&state, stream).await {
+                            log::debug!("Failed to answer a health request: {}", e);
+                        }
+                    });
+                }
+                Err(e) => log::warn!("Failed to accept a health connection: {}", e),
+            }
+        }
+    });
+}
+
+async fn respond(state: &HealthState, stream: TcpStream) -> std::io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader.take(MAX_REQUEST));
+
+    let mut request_line = String::new();
+    let read_head = async {
+        reader.read_line(&mut request_line).await?;
+        // Headers are not needed, but are read so the client doesn't see a reset
+        let mut header = String::new();
+        while reader.read_line(&mut header).await? > 2 {
+            header.clear();
+        }
+        Ok::<_, std::io::Error>(())
+    };
+    if timeout(REQUEST_TIMEOUT, read_head).await.is_err() {
+        return Ok(());
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+    // Query strings are ignored, some probes add a cache buster
+    let path = path.split('?').next().unwrap_or_default();
+
+    let (status, body) = match (method, path) {
+        ("GET" | "HEAD", "/healthz") => ("200 OK", "ok\n".to_string()),
+        ("GET" | "HEAD", "/readyz") => match state.readiness_problems() {
+            problems if problems.is_empty() => ("200 OK", "ready\n".to_string()),
+            problems => ("503 Service Unavailable", problems.join("\n") + "\n"),
+        },
+        ("GET" | "HEAD", _) => ("404 Not Found", "not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "method not allowed\n".to_string()),
+    };
+
+    let mut response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        body.len()
+    );
+    if method != "HEAD" {
+        response.push_str(&body);
+    }
+    writer.write_all(response.as_bytes()).await?;
+    writer.shutdown().await
+}
+
+/// Requests `path` from the health server at `address`; returns whether it answered 200,
+/// and the body
+pub async fn probe(address: &str, path: &str) -> std::io::Result<(bool, String)> {
+    let exchange = async {
+        let mut stream = TcpStream::connect(address).await?;
+        let request = format!("GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", path, address);
+        stream.write_all(request.as_bytes()).await?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await?;
+        Ok::<_, std::io::Error>(response)
+    };
+    let response = timeout(REQUEST_TIMEOUT, exchange)
+        .await
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "no answer"))??;
+
+    let ok = response.split_whitespace().nth(1) == Some("200");
+    let body = response.split_once("\r\n\r\n").map(|(_, body)| body).unwrap_or_default();
+    Ok((ok, body.to_string()))
+}
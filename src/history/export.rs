@@ -0,0 +1,118 @@
+use chrono::{Days, NaiveDate};
+use serde::Serialize;
+
+use crate::database_actions::{day::Day, DatabaseService, StoreError};
+
+use super::{HistoryFormat, HistoryRecord, FORMAT_NAME, FORMAT_VERSION};
+
+#[derive(Serialize)]
+struct JsonExport<'a> {
+    format: &'a str,
+    version: u32,
+    days: &'a [HistoryRecord],
+}
+
+/// Loads the stored days between `from` and `to` (both inclusive, either open-ended)
+pub async fn load_records(
+    database_service: &DatabaseService,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) -> Result<Vec<HistoryRecord>, StoreError> {
+    let from = from.map(Day::start_of).unwrap_or(mongodb::bson::DateTime::MIN);
+    let to = to
+        .and_then(|to| to.checked_add_days(Days::new(1)))
+        .map(Day::start_of)
+        .unwrap_or(mongodb::bson::DateTime::MAX);
+
+    let days = database_service.get_days_in_range(from, to).await?;
+    Ok(days.iter().map(HistoryRecord::from).collect())
+}
+
+/// Serializes records in the documented exchange format
+pub fn serialize_records(
+    records: &[HistoryRecord],
+    format: HistoryFormat,
+) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+    match format {
+        HistoryFormat::Json => Ok(serde_json::to_vec_pretty(&JsonExport {
+            format: FORMAT_NAME,
+            version: FORMAT_VERSION,
+            days: records,
+        })?),
+        HistoryFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            writer.write_record(["date", "votes_yes", "votes_no", "verdict"])?;
+            for record in records {
+                writer.write_record([
+                    record.date.format(super::DATE_FORMAT).to_string(),
+                    join_ids(&record.votes_yes),
+                    join_ids(&record.votes_no),
+                    record.verdict.map(|v| v.to_string()).unwrap_or_default(),
+                ])?;
+            }
+            Ok(writer.into_inner().map_err(|e| e.into_error())?)
+        }
+    }
+}
+
+fn join_ids(ids: &[i64]) -> String {
+    ids.iter().map(i64::to_string).collect::<Vec<_>>().join(";")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{import::parse_records, parse_date, Verdict};
+    use super::*;
+
+    fn record(date: &str, votes_yes: &[i64], votes_no: &[i64]) -> HistoryRecord {
+        HistoryRecord {
+            date: parse_date(date).unwrap(),
+            votes_yes: votes_yes.to_vec(),
+            votes_no: votes_no.to_vec(),
+            verdict: Verdict::of(votes_yes.len(), votes_no.len()),
+        }
+    }
+
+    fn records() -> Vec<HistoryRecord> {
+        vec![
+            record("2025-03-03", &[1, 2], &[3]),
+            record("2025-03-04", &[], &[4]),
+            record("2025-03-05", &[5], &[6]),
+            record("2025-03-06", &[], &[]),
+        ]
+    }
+
+    fn round_trip(format: HistoryFormat) -> Vec<HistoryRecord> {
+        let exported = serialize_records(&records(), format).unwrap();
+        parse_records(&exported, format, parse_date("2025-03-10").unwrap()).unwrap()
+    }
+
+    #[test]
+    fn csv_export_is_read_back_by_import() {
+        assert_eq!(round_trip(HistoryFormat::Csv), records());
+    }
+
+    #[test]
+    fn json_export_is_read_back_by_import() {
+        assert_eq!(round_trip(HistoryFormat::Json), records());
+    }
+
+    #[test]
+    fn csv_export_matches_the_documented_layout() {
+        let exported = serialize_records(&records()[..2], HistoryFormat::Csv).unwrap();
+        assert_eq!(
+            String::from_utf8(exported).unwrap(),
+            "date,votes_yes,votes_no,verdict\n2025-03-03,1;2,3,late\n2025-03-04,,4,on_time\n"
+        );
+    }
+
+    #[test]
+    fn json_export_names_the_format() {
+        let exported = serialize_records(&records()[3..], HistoryFormat::Json).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&exported).unwrap();
+        assert_eq!(value["format"], FORMAT_NAME);
+        assert_eq!(value["version"], FORMAT_VERSION);
+        assert_eq!(value["days"][0]["date"], "2025-03-06");
+        assert!(value["days"][0]["verdict"].is_null());
+    }
+}
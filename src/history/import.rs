@@ -0,0 +1,464 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use chrono::{Days, NaiveDate};
+use serde::Deserialize;
+
+use crate::database_actions::{day::Day, DatabaseService, StoreError};
+use crate::localization::{tr, tr_args, tr_plural, Lang};
+
+use super::{parse_date, HistoryFormat, HistoryRecord, FORMAT_NAME, FORMAT_VERSION};
+
+/// How many changed days and conflicts a report lists before summarizing the rest
+const REPORT_LIMIT: usize = 20;
+
+/// A record that failed validation, with where it was found in the file
+#[derive(Debug, Clone)]
+pub struct InvalidRecord {
+    pub location: String,
+    pub reason: String,
+}
+
+impl InvalidRecord {
+    fn new(location: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self {
+            location: location.into(),
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for InvalidRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.reason)
+    }
+}
+
+/// Votes an import would add to one day
+#[derive(Debug, Clone)]
+pub struct DayChange {
+    pub date: NaiveDate,
+    /// Whether the day has no document yet
+    pub is_new: bool,
+    pub add_yes: Vec<i64>,
+    pub add_no: Vec<i64>,
+}
+
+/// An imported vote that contradicts the stored one; the stored vote is kept
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    pub date: NaiveDate,
+    pub user_id: i64,
+    pub stored_late: bool,
+}
+
+/// Difference between an import file and the stored history
+#[derive(Debug, Clone, Default)]
+pub struct ImportPlan {
+    pub changes: Vec<DayChange>,
+    pub conflicts: Vec<Conflict>,
+    /// Records that add nothing new
+    pub unchanged: usize,
+}
+
+impl ImportPlan {
+    pub fn added_votes(&self) -> usize {
+        self.changes
+            .iter()
+            .map(|change| change.add_yes.len() + change.add_no.len())
+            .sum()
+    }
+}
+
+#[derive(Deserialize)]
+struct JsonImport {
+    format: String,
+    version: u32,
+    days: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct JsonRecord {
+    date: String,
+    #[serde(default)]
+    votes_yes: Vec<i64>,
+    #[serde(default)]
+    votes_no: Vec<i64>,
+}
+
+/// Parses and validates a file in the exchange format.
+///
+/// Every problem in the file is reported, not just the first one. Dates must not be in
+/// the future and may appear only once; user ids must be positive and may not vote both ways.
+pub fn parse_records(
+    contents: &[u8],
+    format: HistoryFormat,
+    today: NaiveDate,
+) -> Result<Vec<HistoryRecord>, Vec<InvalidRecord>> {
+    let rows = match format {
+        HistoryFormat::Csv => csv_rows(contents)?,
+        HistoryFormat::Json => json_rows(contents)?,
+    };
+
+    let mut records = Vec::new();
+    let mut errors = Vec::new();
+    let mut seen_dates = HashMap::new();
+    for (location, row) in rows {
+        let record = row.and_then(|(date, votes_yes, votes_no)| validate(&date, votes_yes, votes_no, today));
+        match record {
+            Ok(record) => {
+                if let Some(first) = seen_dates.insert(record.date, location.clone()) {
+                    errors.push(InvalidRecord::new(location, format!("date {} already appears at {}", record.date, first)));
+                } else {
+                    records.push(record);
+                }
+            }
+            Err(reason) => errors.push(InvalidRecord::new(location, reason)),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(records)
+    } else {
+        Err(errors)
+    }
+}
+
+type RawRow = (String, Result<(String, Vec<i64>, Vec<i64>), String>);
+
+fn csv_rows(contents: &[u8]) -> Result<Vec<RawRow>, Vec<InvalidRecord>> {
+    let file_error = |e: csv::Error| vec![InvalidRecord::new("file", e.to_string())];
+
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(contents);
+    let headers = reader.headers().map_err(file_error)?.clone();
+    let column = |name: &str| headers.iter().position(|header| header == name);
+    let (Some(date), Some(yes), Some(no)) = (column("date"), column("votes_yes"), column("votes_no")) else {
+        return Err(vec![InvalidRecord::new(
+            "header",
+            "expected columns date, votes_yes and votes_no",
+        )]);
+    };
+
+    let mut rows = Vec::new();
+    for row in reader.records() {
+        let row = row.map_err(file_error)?;
+        let location = format!("line {}", row.position().map(|p| p.line()).unwrap_or_default());
+        let cell = |index: usize| row.get(index).unwrap_or_default();
+        let parsed = split_ids(cell(yes))
+            .and_then(|votes_yes| split_ids(cell(no)).map(|votes_no| (cell(date).to_string(), votes_yes, votes_no)));
+        rows.push((location, parsed));
+    }
+    Ok(rows)
+}
+
+fn json_rows(contents: &[u8]) -> Result<Vec<RawRow>, Vec<InvalidRecord>> {
+    let file: JsonImport = serde_json::from_slice(contents)
+        .map_err(|e| vec![InvalidRecord::new("file", e.to_string())])?;
+    if file.format != FORMAT_NAME || file.version != FORMAT_VERSION {
+        return Err(vec![InvalidRecord::new(
+            "file",
+            format!(
+                "unsupported format '{}' version {}, expected '{}' version {}",
+                file.format, file.version, FORMAT_NAME, FORMAT_VERSION
+            ),
+        )]);
+    }
+
+    Ok(file
+        .days
+        .into_iter()
+        .enumerate()
+        .map(|(index, value)| {
+            let parsed = serde_json::from_value::<JsonRecord>(value)
+                .map(|record| (record.date, record.votes_yes, record.votes_no))
+                .map_err(|e| e.to_string());
+            (format!("record {}", index + 1), parsed)
+        })
+        .collect())
+}
+
+fn split_ids(cell: &str) -> Result<Vec<i64>, String> {
+    cell.split(';')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| id.parse().map_err(|_| format!("'{}' is not a user id", id)))
+        .collect()
+}
+
+fn validate(date: &str, votes_yes: Vec<i64>, votes_no: Vec<i64>, today: NaiveDate) -> Result<HistoryRecord, String> {
+    let date = parse_date(date).ok_or_else(|| format!("invalid date '{}', expected YYYY-MM-DD", date))?;
+    if date > today {
+        return Err(format!("date {} is in the future", date));
+    }
+    if let Some(id) = votes_yes.iter().chain(&votes_no).find(|id| **id <= 0) {
+        return Err(format!("'{}' is not a user id", id));
+    }
+
+    // Deduplicate within the record, keeping the file order
+    let votes_yes = dedup(votes_yes);
+    let votes_no = dedup(votes_no);
+    if let Some(id) = votes_yes.iter().find(|id| votes_no.contains(id)) {
+        return Err(format!("user {} voted both late and on time", id));
+    }
+
+    Ok(HistoryRecord {
+        date,
+        verdict: super::Verdict::of(votes_yes.len(), votes_no.len()),
+        votes_yes,
+        votes_no,
+    })
+}
+
+fn dedup(ids: Vec<i64>) -> Vec<i64> {
+    let mut seen = BTreeSet::new();
+    ids.into_iter().filter(|id| seen.insert(*id)).collect()
+}
+
+/// Compares validated records with the stored days without changing anything
+pub async fn plan_import(
+    database_service: &DatabaseService,
+    records: &[HistoryRecord],
+) -> Result<ImportPlan, StoreError> {
+    let (Some(first), Some(last)) = (
+        records.iter().map(|record| record.date).min(),
+        records.iter().map(|record| record.date).max(),
+    ) else {
+        return Ok(ImportPlan::default());
+    };
+    let to = last
+        .checked_add_days(Days::new(1))
+        .map(Day::start_of)
+        .unwrap_or(mongodb::bson::DateTime::MAX);
+    let stored: HashMap<NaiveDate, Day> = database_service
+        .get_days_in_range(Day::start_of(first), to)
+        .await?
+        .into_iter()
+        .map(|day| (day.naive_date(), day))
+        .collect();
+
+    let mut plan = ImportPlan::default();
+    for record in records {
+        let day = stored.get(&record.date);
+        let mut change = DayChange {
+            date: record.date,
+            is_new: day.is_none(),
+            add_yes: Vec::new(),
+            add_no: Vec::new(),
+        };
+
+        for (ids, is_late) in [(&record.votes_yes, true), (&record.votes_no, false)] {
+            for &user_id in ids {
+                let stored_vote = day.and_then(|day| {
+                    if day.votes_yes.contains(&user_id) {
+                        Some(true)
+                    } else if day.votes_no.contains(&user_id) {
+                        Some(false)
+                    } else {
+                        None
+                    }
+                });
+                match stored_vote {
+                    None if is_late => change.add_yes.push(user_id),
+                    None => change.add_no.push(user_id),
+                    Some(stored_late) if stored_late != is_late => plan.conflicts.push(Conflict {
+                        date: record.date,
+                        user_id,
+                        stored_late,
+                    }),
+                    Some(_) => {}
+                }
+            }
+        }
+
+        if change.is_new || !change.add_yes.is_empty() || !change.add_no.is_empty() {
+            plan.changes.push(change);
+        } else {
+            plan.unchanged += 1;
+        }
+    }
+
+    plan.changes.sort_by_key(|change| change.date);
+    plan.conflicts.sort_by_key(|conflict| (conflict.date, conflict.user_id));
+    Ok(plan)
+}
+
+/// Writes the changes of a plan into the database
+pub async fn apply_import(database_service: &DatabaseService, plan: &ImportPlan) -> Result<(), StoreError> {
+    for change in &plan.changes {
+        database_service
+            .merge_votes(Day::start_of(change.date), &change.add_yes, &change.add_no)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Describes a plan; `committed` tells whether it has already been applied
+pub fn render_plan(lang: Lang, plan: &ImportPlan, committed: bool) -> String {
+    let new_days = plan.changes.iter().filter(|change| change.is_new).count();
+    let mut lines = vec![
+        tr(lang, if committed { "import_committed_title" } else { "import_dry_run_title" }),
+        String::new(),
+        tr_args(
+            lang,
+            "import_totals",
+            &[
+                ("new_days", &new_days),
+                ("updated_days", &(plan.changes.len() - new_days)),
+                ("unchanged_days", &plan.unchanged),
+                ("added_votes", &plan.added_votes()),
+            ],
+        ),
+    ];
+
+    if !plan.changes.is_empty() {
+        lines.push(String::new());
+        for change in plan.changes.iter().take(REPORT_LIMIT) {
+            let key = if change.is_new { "import_day_new" } else { "import_day_updated" };
+            lines.push(tr_args(
+                lang,
+                key,
+                &[
+                    ("date", &change.date),
+                    ("late", &change.add_yes.len()),
+                    ("on_time", &change.add_no.len()),
+                ],
+            ));
+        }
+        push_more(lang, &mut lines, plan.changes.len());
+    }
+
+    if !plan.conflicts.is_empty() {
+        lines.push(String::new());
+        lines.push(tr_plural(lang, "import_conflicts", plan.conflicts.len() as u64, &[]));
+        for conflict in plan.conflicts.iter().take(REPORT_LIMIT) {
+            let kept = tr(lang, if conflict.stored_late { "vote_type_late" } else { "vote_type_unlate" });
+            lines.push(tr_args(
+                lang,
+                "import_conflict",
+                &[("date", &conflict.date), ("user_id", &conflict.user_id), ("kept", &kept)],
+            ));
+        }
+        push_more(lang, &mut lines, plan.conflicts.len());
+    }
+
+    lines.join("\n")
+}
+
+fn push_more(lang: Lang, lines: &mut Vec<String>, total: usize) {
+    if total > REPORT_LIMIT {
+        lines.push(tr_plural(lang, "import_more", (total - REPORT_LIMIT) as u64, &[]));
+    }
+}
+
+/// Lists validation problems, at most [`REPORT_LIMIT`] of them
+pub fn render_invalid(lang: Lang, errors: &[InvalidRecord]) -> String {
+    let mut lines = vec![tr(lang, "import_invalid")];
+    lines.extend(errors.iter().take(REPORT_LIMIT).map(InvalidRecord::to_string));
+    push_more(lang, &mut lines, errors.len());
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::Verdict;
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 3, 10).unwrap()
+    }
+
+    fn date(value: &str) -> NaiveDate {
+        parse_date(value).unwrap()
+    }
+
+    fn csv(contents: &str) -> Result<Vec<HistoryRecord>, Vec<InvalidRecord>> {
+        parse_records(contents.as_bytes(), HistoryFormat::Csv, today())
+    }
+
+    fn json(days: &str) -> Result<Vec<HistoryRecord>, Vec<InvalidRecord>> {
+        let contents = format!(r#"{{"format": "{}", "version": {}, "days": {}}}"#, FORMAT_NAME, FORMAT_VERSION, days);
+        parse_records(contents.as_bytes(), HistoryFormat::Json, today())
+    }
+
+    fn reasons(errors: Vec<InvalidRecord>) -> Vec<String> {
+        errors.iter().map(InvalidRecord::to_string).collect()
+    }
+
+    #[test]
+    fn parses_csv_with_extra_columns_and_spaces() {
+        let records = csv("verdict,date,votes_yes,votes_no\nlate, 2025-03-03 ,1; 2,3\n,2025-03-04,,\n").unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].date, date("2025-03-03"));
+        assert_eq!((records[0].votes_yes.as_slice(), records[0].votes_no.as_slice()), (&[1, 2][..], &[3][..]));
+        assert_eq!(records[0].verdict, Some(Verdict::Late));
+        assert!(records[1].votes_yes.is_empty() && records[1].verdict.is_none());
+    }
+
+    #[test]
+    fn parses_json_with_missing_vote_lists() {
+        let records = json(r#"[{"date": "2025-03-03", "votes_no": [4]}]"#).unwrap();
+        assert_eq!(records[0].votes_no, vec![4]);
+        assert!(records[0].votes_yes.is_empty());
+    }
+
+    #[test]
+    fn reports_every_malformed_csv_row() {
+        let errors =
+            csv("date,votes_yes,votes_no\n03.03.2025,1,\n2025-03-04,abc,\n2025-03-05,0,\n2025-03-06,1,\n").unwrap_err();
+        assert_eq!(
+            reasons(errors),
+            vec![
+                "line 2: invalid date '03.03.2025', expected YYYY-MM-DD",
+                "line 3: 'abc' is not a user id",
+                "line 4: '0' is not a user id",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_malformed_json_records() {
+        let errors = json(r#"[{"date": "2025-03-03", "votes_yes": ["x"]}, {"votes_yes": [1]}, {"date": "2025-03-05"}]"#)
+            .unwrap_err();
+        let locations: Vec<&str> = errors.iter().map(|error| error.location.as_str()).collect();
+        assert_eq!(locations, vec!["record 1", "record 2"]);
+    }
+
+    #[test]
+    fn rejects_files_it_cannot_read_as_a_whole() {
+        assert_eq!(
+            reasons(csv("day,late\n2025-03-03,1\n").unwrap_err()),
+            vec!["header: expected columns date, votes_yes and votes_no"]
+        );
+        let wrong_version = r#"{"format": "latebot-history", "version": 99, "days": []}"#;
+        let errors = parse_records(wrong_version.as_bytes(), HistoryFormat::Json, today()).unwrap_err();
+        assert_eq!(errors[0].location, "file");
+        assert!(parse_records(b"{", HistoryFormat::Json, today()).is_err());
+    }
+
+    #[test]
+    fn duplicate_dates_point_at_the_first_occurrence() {
+        let errors = csv("date,votes_yes,votes_no\n2025-03-03,1,\n2025-03-04,2,\n2025-03-03,,3\n").unwrap_err();
+        assert_eq!(reasons(errors), vec!["line 4: date 2025-03-03 already appears at line 2"]);
+
+        let errors = json(r#"[{"date": "2025-03-03"}, {"date": "2025-03-03"}]"#).unwrap_err();
+        assert_eq!(reasons(errors), vec!["record 2: date 2025-03-03 already appears at record 1"]);
+    }
+
+    #[test]
+    fn validate_rejects_future_dates_and_double_votes() {
+        let future = validate("2025-03-11", vec![], vec![], today());
+        assert_eq!(future.unwrap_err(), "date 2025-03-11 is in the future");
+        assert!(validate("2025-03-10", vec![], vec![], today()).is_ok());
+        let both_ways = validate("2025-03-03", vec![1, 2], vec![2], today());
+        assert_eq!(both_ways.unwrap_err(), "user 2 voted both late and on time");
+        assert_eq!(validate("2025-03-03", vec![-5], vec![], today()).unwrap_err(), "'-5' is not a user id");
+    }
+
+    #[test]
+    fn validate_drops_repeated_ids_and_sets_the_verdict() {
+        let record = validate("2025-03-03", vec![3, 1, 3], vec![2, 2, 4], today()).unwrap();
+        assert_eq!(record.votes_yes, vec![3, 1]);
+        assert_eq!(record.votes_no, vec![2, 4]);
+        assert_eq!(record.verdict, Some(Verdict::Tie));
+    }
+}
@@ -0,0 +1,144 @@
+//! Exchange format for the voting history.
+//!
+//! Both formats carry one record per stored day:
+//!
+//! | field       | type                              | meaning                                     |
+//! |-------------|-----------------------------------|---------------------------------------------|
+//! | `date`      | `YYYY-MM-DD`                      | calendar day (UTC)                          |
+//! | `votes_yes` | list of Telegram user ids         | users who voted "late"                      |
+//! | `votes_no`  | list of Telegram user ids         | users who voted "on time"                   |
+//! | `verdict`   | `late`, `on_time`, `tie` or empty | outcome of the day; empty when nobody voted |
+//!
+//! CSV files have a header row `date,votes_yes,votes_no,verdict` and separate user ids
+//! within a cell with `;`. JSON files are an object
+//! `{"format": "latebot-history", "version": 1, "days": [...]}` with one object per record.
+//! `verdict` is informational and derived from the votes; imports ignore it.
+
+use std::fmt;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::database_actions::day::Day;
+
+pub mod export;
+pub mod import;
+
+/// Value of the `format` field in JSON exports
+pub const FORMAT_NAME: &str = "latebot-history";
+/// Current version of the exchange format
+pub const FORMAT_VERSION: u32 = 1;
+/// Date format used by the exchange format and by commands accepting dates
+pub const DATE_FORMAT: &str = "%Y-%m-%d";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryFormat {
+    Csv,
+    Json,
+}
+
+impl HistoryFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "csv" => Some(HistoryFormat::Csv),
+            "json" => Some(HistoryFormat::Json),
+            _ => None,
+        }
+    }
+
+    /// Guesses the format of an uploaded file from its name, then from its first byte
+    pub fn detect(file_name: Option<&str>, contents: &[u8]) -> Self {
+        let by_extension = file_name
+            .and_then(|name| name.rsplit_once('.'))
+            .and_then(|(_, extension)| Self::parse(extension));
+
+        by_extension.unwrap_or_else(|| {
+            match contents.iter().find(|byte| !byte.is_ascii_whitespace()) {
+                Some(b'{') => HistoryFormat::Json,
+                _ => HistoryFormat::Csv,
+            }
+        })
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            HistoryFormat::Csv => "csv",
+            HistoryFormat::Json => "json",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Late,
+    OnTime,
+    Tie,
+}
+
+impl Verdict {
+    pub fn of(votes_yes: usize, votes_no: usize) -> Option<Self> {
+        match (votes_yes, votes_no) {
+            (0, 0) => None,
+            (yes, no) if yes > no => Some(Verdict::Late),
+            (yes, no) if no > yes => Some(Verdict::OnTime),
+            _ => Some(Verdict::Tie),
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Verdict::Late => "late",
+            Verdict::OnTime => "on_time",
+            Verdict::Tie => "tie",
+        }
+    }
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// One day of history in the exchange format
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryRecord {
+    #[serde(with = "date_format")]
+    pub date: NaiveDate,
+    pub votes_yes: Vec<i64>,
+    pub votes_no: Vec<i64>,
+    #[serde(default)]
+    pub verdict: Option<Verdict>,
+}
+
+impl From<&Day> for HistoryRecord {
+    fn from(day: &Day) -> Self {
+        Self {
+            date: day.naive_date(),
+            votes_yes: day.votes_yes.clone(),
+            votes_no: day.votes_no.clone(),
+            verdict: Verdict::of(day.votes_yes.len(), day.votes_no.len()),
+        }
+    }
+}
+
+/// Parses a `YYYY-MM-DD` date
+pub fn parse_date(value: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value, DATE_FORMAT).ok()
+}
+
+mod date_format {
+    use chrono::NaiveDate;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(date: &NaiveDate, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&date.format(super::DATE_FORMAT))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveDate, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        super::parse_date(&value)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid date '{}', expected YYYY-MM-DD", value)))
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+use teloxide::types::User;
+
+use crate::database_actions::DatabaseService;
+
+/// Languages the bot has message catalogs for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Lang {
+    #[default]
+    Ru,
+    En,
+}
+
+impl Lang {
+    /// Parses an ISO 639-1 code or a Telegram `language_code` such as `en-US`
+    pub fn from_code(code: &str) -> Option<Self> {
+        let primary = code.split(['-', '_']).next().unwrap_or_default();
+        match primary.to_lowercase().as_str() {
+            "ru" => Some(Lang::Ru),
+            "en" => Some(Lang::En),
+            _ => None,
+        }
+    }
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Lang::Ru => "ru",
+            Lang::En => "en",
+        }
+    }
+
+    /// CLDR plural category of `n` in this language
+    fn plural_category(self, n: u64) -> &'static str {
+        match self {
+            Lang::Ru => {
+                let (rem10, rem100) = (n % 10, n % 100);
+                if rem10 == 1 && rem100 != 11 {
+                    "one"
+                } else if (2..=4).contains(&rem10) && !(12..=14).contains(&rem100) {
+                    "few"
+                } else {
+                    "many"
+                }
+            }
+            Lang::En => {
+                if n == 1 {
+                    "one"
+                } else {
+                    "other"
+                }
+            }
+        }
+    }
+}
+
+/// A catalog entry: either a plain message or a set of plural forms
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Entry {
+    Text(String),
+    Plural(HashMap<String, String>),
+}
+
+type Catalog = HashMap<String, Entry>;
+
+/// A named placeholder value substituted into a message
+pub type Arg<'a> = (&'a str, &'a (dyn Display + Sync));
+
+fn catalog(lang: Lang) -> &'static Catalog {
+    static RU: OnceLock<Catalog> = OnceLock::new();
+    static EN: OnceLock<Catalog> = OnceLock::new();
+
+    match lang {
+        Lang::Ru => RU.get_or_init(|| parse_catalog("ru", include_str!("../../locales/ru.json"))),
+        Lang::En => EN.get_or_init(|| parse_catalog("en", include_str!("../../locales/en.json"))),
+    }
+}
+
+fn parse_catalog(name: &str, source: &str) -> Catalog {
+    serde_json::from_str(source)
+        .unwrap_or_else(|e| panic!("Message catalog '{}' is malformed: {}", name, e))
+}
+
+/// Looks up `key` in the catalog of `lang`, falling back to Russian
+fn lookup(lang: Lang, key: &str) -> Option<&'static Entry> {
+    lookup_in(catalog(lang), catalog(Lang::Ru), key)
+}
+
+fn lookup_in<'a>(catalog: &'a Catalog, fallback: &'a Catalog, key: &str) -> Option<&'a Entry> {
+    catalog.get(key).or_else(|| fallback.get(key))
+}
+
+/// Replaces `{name}` placeholders in `template` with the matching `args`
+pub fn substitute(template: &str, args: &[Arg]) -> String {
+    let mut result = template.to_string();
+    for (name, value) in args {
+        result = result.replace(&format!("{{{}}}", name), &value.to_string());
+    }
+    result
+}
+
+/// Returns the message `key` in `lang`
+pub fn tr(lang: Lang, key: &str) -> String {
+    tr_args(lang, key, &[])
+}
+
+/// Returns the message `key` in `lang` with `{name}` placeholders substituted
+pub fn tr_args(lang: Lang, key: &str, args: &[Arg]) -> String {
+    match lookup(lang, key) {
+        Some(Entry::Text(text)) => substitute(text, args),
+        Some(Entry::Plural(_)) => tr_plural(lang, key, 0, args),
+        None => {
+            log::warn!("Missing message catalog key: {}", key);
+            key.to_string()
+        }
+    }
+}
+
+/// Returns the plural form of message `key` matching `count`.
+///
+/// The `{count}` placeholder is always available in addition to `args`.
+pub fn tr_plural(lang: Lang, key: &str, count: u64, args: &[Arg]) -> String {
+    let mut all_args: Vec<Arg> = vec![("count", &count)];
+    all_args.extend_from_slice(args);
+
+    match lookup(lang, key) {
+        Some(Entry::Plural(forms)) => {
+            let form = forms
+                .get(lang.plural_category(count))
+                .or_else(|| forms.get("other"))
+                .or_else(|| forms.get("many"))
+                .map(String::as_str)
+                .unwrap_or(key);
+            substitute(form, &all_args)
+        }
+        Some(Entry::Text(text)) => substitute(text, &all_args),
+        None => {
+            log::warn!("Missing message catalog key: {}", key);
+            key.to_string()
+        }
+    }
+}
+
+/// Picks the language from the user's Telegram `language_code` only.
+///
+/// Used on hot paths such as rate-limit replies where a database lookup is undesirable.
+pub fn user_lang(user: Option<&User>, default: Lang) -> Lang {
+    user.and_then(|u| u.language_code.as_deref())
+        .and_then(Lang::from_code)
+        .unwrap_or(default)
+}
+
+/// Picks the language for a reply: the chat setting wins, then the user's `language_code`,
+/// then the configured default.
+pub async fn resolve_lang(
+    database_service: &DatabaseService,
+    chat_id: Option<i64>,
+    user: Option<&User>,
+    default: Lang,
+) -> Lang {
+    // While the database is down every lookup would wait for the server selection timeout
+    if let Some(chat_id) = chat_id.filter(|_| database_service.is_available()) {
+        match database_service.get_chat_language(chat_id).await {
+            Ok(Some(code)) => {
+                if let Some(lang) = Lang::from_code(&code) {
+                    return lang;
+                }
+            }
+            Ok(None) => {}
+            Err(e) => log::error!("Failed to load chat language for {}: {}", chat_id, e),
+        }
+    }
+    user_lang(user, default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn russian_plural_categories() {
+        let cases = [
+            (1, "one"),
+            (2, "few"),
+            (5, "many"),
+            (11, "many"),
+            (12, "many"),
+            (21, "one"),
+            (22, "few"),
+            (25, "many"),
+            (111, "many"),
+        ];
+        for (n, category) in cases {
+            assert_eq!(Lang::Ru.plural_category(n), category, "n = {}", n);
+        }
+    }
+
+    #[test]
+    fn russian_plural_forms() {
+        assert_eq!(tr_plural(Lang::Ru, "votes", 1, &[]), "1 голос");
+        assert_eq!(tr_plural(Lang::Ru, "votes", 2, &[]), "2 голоса");
+        assert_eq!(tr_plural(Lang::Ru, "votes", 5, &[]), "5 голосов");
+        assert_eq!(tr_plural(Lang::Ru, "votes", 11, &[]), "11 голосов");
+        assert_eq!(tr_plural(Lang::Ru, "votes", 21, &[]), "21 голос");
+        assert_eq!(tr_plural(Lang::Ru, "votes", 111, &[]), "111 голосов");
+    }
+
+    #[test]
+    fn english_plural_forms() {
+        assert_eq!(Lang::En.plural_category(1), "one");
+        assert_eq!(Lang::En.plural_category(2), "other");
+        assert_eq!(tr_plural(Lang::En, "votes", 1, &[]), "1 vote");
+        assert_eq!(tr_plural(Lang::En, "votes", 2, &[]), "2 votes");
+    }
+
+    #[test]
+    fn missing_key_falls_back_to_the_other_catalog() {
+        let en = parse_catalog("en", r#"{"greeting": "Hello"}"#);
+        let ru = parse_catalog("ru", r#"{"greeting": "Привет", "farewell": "Пока"}"#);
+        assert!(matches!(lookup_in(&en, &ru, "greeting"), Some(Entry::Text(text)) if text == "Hello"));
+        assert!(matches!(lookup_in(&en, &ru, "farewell"), Some(Entry::Text(text)) if text == "Пока"));
+        assert!(lookup_in(&en, &ru, "unknown").is_none());
+    }
+
+    #[test]
+    fn catalogs_have_the_same_keys() {
+        let mut ru: Vec<&String> = catalog(Lang::Ru).keys().collect();
+        let mut en: Vec<&String> = catalog(Lang::En).keys().collect();
+        ru.sort();
+        en.sort();
+        assert_eq!(ru, en);
+    }
+}
@@ -1,44 +1,161 @@
 use database_actions::DatabaseService;
-use mongodb::bson::DateTime;
-use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-use teloxide::prelude::*;
+use teloxide::{
+    prelude::*,
+    types::{InlineQuery, PollAnswer},
+};
 
+pub mod charts;
+pub mod cli;
 pub mod config;
 pub mod console;
 pub mod database_actions;
+pub mod digest;
 pub mod handlers;
+pub mod health;
+pub mod history;
+pub mod localization;
+pub mod milestones;
+pub mod scheduler;
 pub mod securiy;
 
-use config::Config;
+use clap::Parser;
+use cli::{Cli, Command};
+use arc_swap::ArcSwap;
+use config::{reload::ConfigReloader, Config, SharedBotConfig};
+use localization::{tr, user_lang};
 use securiy::manager::SecurityManager;
 
 #[tokio::main]
 async fn main() {
+    let mut cli = Cli::parse();
+    let command = match cli.command.take() {
+        _ if cli.check_config => Command::CheckConfig,
+        Some(command) => command,
+        None => Command::Run,
+    };
+
+    if let Command::Config { command } = command {
+        if let Err(e) = cli::run_config_command(command) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Configuration: defaults, then the config file, then LATEBOT_* environment variables
+    let config_path = cli.config_path();
+    let config_required = cli.config_required();
+    if let Command::CheckConfig = command {
+        match Config::load(&config_path, config_required, cli.allow_defaults) {
+            Ok((_, sources)) => {
+                println!("Configuration is valid");
+                for line in config::layers::describe_sources(&sources) {
+                    println!("  {}", line);
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
     use flexi_logger::{Logger, Duplicate, FileSpec};
     use chrono::Local;
-    let now = Local::now();
-    let logfile_name = format!("latebot-{}", now.format("%Y-%m-%d_%H-%M-%S"));
-    Logger::try_with_str("info")
-        .unwrap()
-        .log_to_file(FileSpec::default().directory(".").basename(&logfile_name).suppress_timestamp())
-        .duplicate_to_stdout(Duplicate::Info)
-        .start()
-        .unwrap();
+    let logger = Logger::try_with_str(cli.log_level.spec()).unwrap();
+    // Maintenance commands print their results to stdout, so their logs only go to stderr
+    let logger = if let Command::Run = command {
+        let now = Local::now();
+        let logfile_name = format!("latebot-{}", now.format("%Y-%m-%d_%H-%M-%S"));
+        logger
+            .log_to_file(FileSpec::default().directory(&cli.log_dir).basename(&logfile_name).suppress_timestamp())
+            .duplicate_to_stdout(Duplicate::All)
+    } else {
+        logger
+    };
+    // Credentials are registered as the config loads and removed from every log line
+    let logger = logger.format(config::secret::redacting_format);
+    let _logger = match logger.start() {
+        Ok(logger) => logger,
+        Err(e) => {
+            eprintln!("Failed to start logging: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let config = match Config::load(&config_path, config_required, cli.allow_defaults) {
+        Ok((config, sources)) => {
+            // The output of these clients is the bot's answer alone
+            if !matches!(command, Command::Admin { .. } | Command::Healthcheck { .. }) {
+                for line in config::layers::describe_sources(&sources) {
+                    log::info!("Config {}", line);
+                }
+            }
+            config
+        }
+        Err(e) => {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if let Command::Admin { socket, port, command } = command {
+        let result = match cli::admin::Endpoint::resolve(socket, port, &config.admin) {
+            Ok(endpoint) => cli::admin::run(endpoint, command).await,
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = result {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Command::Healthcheck { address, live } = command {
+        if let Err(e) = cli::healthcheck::run(address, live, &config.health).await {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if !matches!(command, Command::Run) {
+        if let Err(e) = cli::maintenance::run(command, &config).await {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     log::info!("Starting late tracking bot...");
+    let token = match config.bot.resolve_token() {
+        Ok(token) => token,
+        Err(e) => {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    config::secret::register(token.expose());
+    let bot = match bot_from_env(&token) {
+        Ok(bot) => bot,
+        Err(e) => {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    check_token(&bot).await;
 
-    // Load configuration from config.json
-    let config = Config::load_or_default("config.json");
-    
-    let target_name = config.bot.target_name;
-    let notification_chat_id = config.bot.notification_chat_id;
-    let ping_user = config.bot.ping_user;
+    let bot_config: SharedBotConfig = Arc::new(ArcSwap::from_pointee(config.bot.clone()));
     
-    // Start console interface
-    console::start_console_interface().await;
+    if let Err(e) = charts::load_font(&config.charts.font_path) {
+        log::warn!("Charts will be unavailable: {}", e);
+    }
+
 
     // Initialize security manager
-    let security_config = config.security;
+    let security_config = config.security.clone();
 
     log::info!(
         "Initializing security manager with rate limit: {} requests per {} seconds",
@@ -48,18 +165,60 @@ async fn main() {
     let security_manager = Arc::new(SecurityManager::new(security_config).await);
 
     let database_service =
-        database_actions::DatabaseServiceInner::new(&config.database.connection_uri).await;
-    let bot = Bot::from_env();
+        match database_actions::DatabaseServiceInner::new(&config.database).await {
+            Ok(database_service) => database_service,
+            Err(e) => {
+                log::error!("Failed to initialize the database client: {}", e);
+                std::process::exit(1);
+            }
+        };
+    let reloader = Arc::new(ConfigReloader::new(
+        config_path,
+        config_required,
+        cli.allow_defaults,
+        &config,
+        bot_config.clone(),
+        security_manager.clone(),
+    ));
+    config::reload::start_config_watcher(reloader.clone());
+
+    // Started before waiting for the database, so /healthz answers during a long wait
+    let polling = Arc::new(health::PollingStatus::default());
+    if !config.health.bind_address.is_empty() {
+        let health_state = Arc::new(health::HealthState {
+            database_service: database_service.clone(),
+            polling: polling.clone(),
+        });
+        health::server::start_health_server(health_state, &config.health.bind_address).await;
+    }
+
+    database_service.wait_until_available(&config.database).await;
+    database_actions::health::start_health_checker(database_service.clone(), &config.database);
+
+    let console_context = Arc::new(console::ConsoleContext {
+        bot: bot.clone(),
+        database_service: database_service.clone(),
+        security_manager: security_manager.clone(),
+        bot_config: bot_config.clone(),
+        reloader,
+    });
+    console::remote::start_admin_server(console_context.clone(), &config.admin).await;
+
+    scheduler::start_scheduler(
+        bot.clone(),
+        database_service.clone(),
+        bot_config.clone(),
+        config.scheduler.clone(),
+    );
 
     let handler = dptree::entry()
         .branch(Update::filter_message().endpoint(
             |bot: Bot,
              msg: Message,
-             target_name: String,
-             ping_user: String,
              database_service: DatabaseService,
-             notification_chat_id: i64,
+             bot_config: SharedBotConfig,
              security_manager: Arc<SecurityManager>| async move {
+                let bot_config = bot_config.load_full();
                 // Get user ID for rate limiting
                 if let Some(user) = &msg.from {
                     let user_id = user.id.0 as i64;
@@ -68,11 +227,9 @@ async fn main() {
                     // Check if the request is allowed by the rate limiter
                     if !security_manager.handle_request(user_id).await {
                         // If rate limit exceeded, inform the user and don't process the request
+                        let lang = user_lang(Some(user), bot_config.default_lang());
                         let _ = bot
-                            .send_message(
-                                msg.chat.id,
-                                "⚠️ Слишком много запросов. Пожалуйста, попробуйте позже.",
-                            )
+                            .send_message(msg.chat.id, tr(lang, "rate_limited"))
                             .await;
                         return Ok(());
                     }
@@ -82,10 +239,8 @@ async fn main() {
                 handlers::message_handler(
                     bot,
                     msg,
-                    target_name,
-                    ping_user,
                     database_service,
-                    notification_chat_id,
+                    bot_config,
                 )
                 .await
             },
@@ -94,8 +249,10 @@ async fn main() {
             |bot: Bot,
              q: CallbackQuery,
              database_service: DatabaseService,
+             bot_config: SharedBotConfig,
              security_manager: Arc<SecurityManager>| async move {
-                
+                let bot_config = bot_config.load_full();
+
                 // Get user ID for rate limiting
                 let user = q.from.id;
                 let user_id = user.0 as i64;
@@ -106,9 +263,10 @@ async fn main() {
                 if !security_manager.handle_request(user_id).await {
                     // If rate limit exceeded, answer the callback query with an error message
                     let id = q.id.as_str();
+                    let lang = user_lang(Some(&q.from), bot_config.default_lang());
                     let _ = bot
                         .answer_callback_query(id)
-                        .text("⚠️ Слишком много запросов. Пожалуйста, попробуйте позже.")
+                        .text(tr(lang, "rate_limited"))
                         .show_alert(true)
                         .await;
 
@@ -116,28 +274,111 @@ async fn main() {
                 }
 
                 // Proceed with normal callback handling
-                handlers::handle_callback(bot, q, database_service).await
+                handlers::handle_callback(bot, q, database_service, bot_config).await
+            },
+        ))
+        .branch(Update::filter_inline_query().endpoint(
+            |bot: Bot,
+             q: InlineQuery,
+             database_service: DatabaseService,
+             bot_config: SharedBotConfig,
+             security_manager: Arc<SecurityManager>| async move {
+                let bot_config = bot_config.load_full();
+                let user_id = q.from.id.0 as i64;
+                log::info!("Inline query from user: {}", user_id);
+
+                // A rate-limited inline query is left unanswered; Telegram shows no results
+                if !security_manager.handle_request(user_id).await {
+                    return Ok(());
+                }
+
+                handlers::handle_inline_query(bot, q, database_service, bot_config).await
+            },
+        ))
+        .branch(Update::filter_poll_answer().endpoint(
+            |bot: Bot,
+             answer: PollAnswer,
+             database_service: DatabaseService,
+             bot_config: SharedBotConfig,
+             security_manager: Arc<SecurityManager>| async move {
+                let bot_config = bot_config.load_full();
+                if let Some(user) = answer.voter.user() {
+                    let user_id = user.id.0 as i64;
+                    log::info!("Poll answer from user: {}", user_id);
+
+                    if security_manager.is_blocked(user_id).await {
+                        log::warn!("Ignoring poll answer from blocked user: {}", user_id);
+                        return Ok(());
+                    }
+                    // Telegram already shows the answer, so dropping it would leave the poll and
+                    // the tally disagreeing for good; Telegram itself limits how fast it can change
+                    if !security_manager.handle_request(user_id).await {
+                        log::info!("Recording poll answer over the rate limit from user: {}", user_id);
+                    }
+                }
+
+                handlers::handle_poll_answer(bot, answer, database_service, bot_config).await
             },
         ));
 
-    Dispatcher::builder(bot, handler)
+    let listener = teloxide::update_listeners::polling_default(bot.clone()).await;
+    let mut dispatcher = Dispatcher::builder(bot, handler)
         .dependencies(dptree::deps![
-            target_name,
-            ping_user,
             database_service,
-            notification_chat_id,
+            bot_config,
             security_manager
         ])
         .enable_ctrlc_handler()
-        .build()
-        .dispatch()
-        .await;
+        .build();
+
+    // Start console interface
+    console::start_console_interface(console_context, dispatcher.shutdown_token());
+
+    // Same as `dispatch()`, with polling errors also reported to /readyz
+    polling.set_running(true);
+    dispatcher.dispatch_with_listener(listener, polling.clone()).await;
+    polling.set_running(false);
+    log::info!("Bot stopped");
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Vote {
-    user_id: i64,
-    username: String,
-    is_late: bool,
-    timestamp: DateTime,
+/// A bot logging in with `token`, otherwise configured like `Bot::from_env`: through
+/// `TELOXIDE_PROXY` and `TELOXIDE_API_URL`
+fn bot_from_env(token: &config::secret::Secret) -> Result<Bot, String> {
+    let bot = Bot::with_client(token.expose(), teloxide::net::client_from_env());
+    match std::env::var("TELOXIDE_API_URL") {
+        Ok(api_url) => url::Url::parse(&api_url)
+            .map(|api_url| bot.set_api_url(api_url))
+            .map_err(|e| format!("TELOXIDE_API_URL is not a valid URL: {}", e)),
+        Err(_) => Ok(bot),
+    }
+}
+
+/// Logs in with `getMe` and exits with a clear message if Telegram rejects the token.
+///
+/// Other failures, such as the network not being up yet when the container starts, are
+/// retried with backoff until Telegram answers.
+async fn check_token(bot: &Bot) {
+    use std::time::Duration;
+    use teloxide::{ApiError, RequestError};
+
+    const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+    let mut delay = Duration::from_secs(1);
+    loop {
+        match bot.get_me().await {
+            Ok(me) => return log::info!("Logged in to Telegram as @{}", me.username()),
+            Err(RequestError::Api(ApiError::InvalidToken)) => {
+                log::error!("Telegram rejected the bot token; check bot.token, bot.token_file or TELOXIDE_TOKEN");
+                std::process::exit(1);
+            }
+            Err(e) => {
+                log::warn!(
+                    "Failed to check the bot token with Telegram, retrying in {}s: {}",
+                    delay.as_secs(),
+                    e
+                );
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(MAX_RETRY_DELAY);
+            }
+        }
+    }
 }
@@ -0,0 +1,39 @@
+use serde::{Deserialize, Serialize};
+
+/// What has to happen for a milestone to fire
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MilestoneKind {
+    /// The first vote of the day, in either direction
+    FirstVote,
+    /// Every time the number of "late" votes reaches a multiple of `count`
+    Every,
+    /// When the number of "late" votes reaches `count`
+    At,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct MilestoneRule {
+    pub kind: MilestoneKind,
+    /// Vote threshold for `every` and `at` rules; ignored for `first_vote`
+    #[serde(default)]
+    pub count: u32,
+    /// Announce this rule at most once per day, even if it matches again
+    #[serde(default)]
+    pub once_per_day: bool,
+    /// Announcement text. Supports `{count}`, `{people}`, `{voters}`, `{target}` and `{ping}`
+    /// placeholders; when omitted the localized default message is used
+    #[serde(default)]
+    pub template: Option<String>,
+}
+
+/// Rules that reproduce the historical behaviour: a celebration every 5 "late" votes
+pub fn default_milestones() -> Vec<MilestoneRule> {
+    vec![MilestoneRule {
+        kind: MilestoneKind::Every,
+        count: 5,
+        once_per_day: false,
+        template: None,
+    }]
+}
@@ -0,0 +1,109 @@
+use teloxide::{prelude::*, RequestError};
+
+use crate::config::BotConfig;
+use crate::database_actions::{day::Day, DatabaseService};
+use crate::localization::{resolve_lang, substitute, tr_args, tr_plural, Arg, Lang};
+
+use config::{MilestoneKind, MilestoneRule};
+
+pub mod config;
+
+impl MilestoneRule {
+    /// Key under which a fired rule is remembered in the day document.
+    ///
+    /// Repeating rules include the reached vote count so that retracting and re-casting
+    /// a vote does not announce the same threshold twice.
+    fn claim_key(&self, late_votes: usize) -> String {
+        match (self.kind, self.once_per_day) {
+            (MilestoneKind::FirstVote, _) => "first_vote".to_string(),
+            (MilestoneKind::Every, true) => format!("every:{}", self.count),
+            (MilestoneKind::Every, false) => format!("every:{}@{}", self.count, late_votes),
+            (MilestoneKind::At, _) => format!("at:{}", self.count),
+        }
+    }
+
+    // `usize::is_multiple_of` needs Rust 1.87
+    #[allow(clippy::manual_is_multiple_of)]
+    fn matches(&self, day: &Day, is_late: bool) -> bool {
+        let late_votes = day.votes_yes.len();
+        match self.kind {
+            MilestoneKind::FirstVote => !day.votes_yes.is_empty() || !day.votes_no.is_empty(),
+            MilestoneKind::Every => {
+                is_late && self.count > 0 && late_votes > 0 && late_votes % self.count as usize == 0
+            }
+            MilestoneKind::At => is_late && self.count > 0 && late_votes >= self.count as usize,
+        }
+    }
+
+    fn render(&self, lang: Lang, day: &Day, bot_config: &BotConfig) -> String {
+        let late_votes = day.votes_yes.len() as u64;
+        let voters = (day.votes_yes.len() + day.votes_no.len()) as u64;
+        let people = tr_plural(lang, "people", late_votes, &[]);
+        let args: [Arg; 5] = [
+            ("count", &late_votes),
+            ("people", &people),
+            ("voters", &voters),
+            ("target", &bot_config.target_name),
+            ("ping", &bot_config.ping_user),
+        ];
+
+        match (&self.template, self.kind) {
+            (Some(template), _) => substitute(template, &args),
+            (None, MilestoneKind::FirstVote) => tr_args(lang, "milestone_first_vote", &args),
+            (None, _) => tr_plural(lang, "celebration", late_votes, &args[1..]),
+        }
+    }
+}
+
+/// Evaluates the milestone rules against the freshly updated `day` and posts every
+/// announcement that fired to the notification chat.
+///
+/// Each announcement is claimed atomically in the day document first, so concurrent votes
+/// and different entry points never announce the same milestone twice.
+pub async fn announce_milestones(
+    bot: &Bot,
+    database_service: &DatabaseService,
+    bot_config: &BotConfig,
+    day: &Day,
+    is_late: bool,
+) -> Result<(), RequestError> {
+    if bot_config.notification_chat_id == 0 {
+        return Ok(());
+    }
+
+    let mut lang = None;
+    for rule in bot_config.milestones.iter().filter(|rule| rule.matches(day, is_late)) {
+        let key = rule.claim_key(day.votes_yes.len());
+        match database_service.claim_milestone(day.date, &key).await {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(e) => {
+                log::error!("Failed to claim milestone {}: {}", key, e);
+                continue;
+            }
+        }
+
+        // The announcement goes to another chat, so it uses that chat's language
+        let lang = match lang {
+            Some(lang) => lang,
+            None => {
+                let resolved = resolve_lang(
+                    database_service,
+                    Some(bot_config.notification_chat_id),
+                    None,
+                    bot_config.default_lang(),
+                )
+                .await;
+                *lang.insert(resolved)
+            }
+        };
+
+        log::info!("Milestone {} reached", key);
+        bot.send_message(
+            ChatId(bot_config.notification_chat_id),
+            rule.render(lang, day, bot_config),
+        )
+        .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+
+/// Times of the automatic posts to `notification_chat_id`.
+///
+/// Each job takes a five-field cron expression (`minute hour day-of-month month day-of-week`)
+/// evaluated in the zone given by `bot.utc_offset_minutes`; a job without an expression is disabled.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SchedulerConfig {
+    /// A run missed by at most this many minutes (e.g. during a restart) is still performed
+    #[serde(default = "default_catch_up_minutes")]
+    pub catch_up_minutes: u32,
+    /// Posts the voting keyboard or poll, e.g. `"0 9 * * 1-5"`
+    #[serde(default)]
+    pub morning_post: Option<String>,
+    /// Reminds users who voted on the previous voting day (within a week) but haven't voted today
+    #[serde(default)]
+    pub reminder: Option<String>,
+    /// Posts the day's result
+    #[serde(default)]
+    pub daily_summary: Option<String>,
+    /// Posts the digest of the previous week, e.g. `"0 10 * * 1"`
+    #[serde(default)]
+    pub weekly_digest: Option<String>,
+    /// Posts the digest of the previous month, e.g. `"0 10 1 * *"`
+    #[serde(default)]
+    pub monthly_digest: Option<String>,
+}
+
+fn default_catch_up_minutes() -> u32 {
+    15
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            catch_up_minutes: default_catch_up_minutes(),
+            morning_post: None,
+            reminder: None,
+            daily_summary: None,
+            weekly_digest: None,
+            monthly_digest: None,
+        }
+    }
+}
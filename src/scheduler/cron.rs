@@ -0,0 +1,273 @@
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Timelike};
+
+/// A parsed five-field cron expression: `minute hour day-of-month month day-of-week`.
+///
+/// Each field accepts `*`, single values, ranges (`1-5`), lists (`1,3,5`) and steps
+/// (`*/15`, `8-18/2`). Day of week is `0-7` with both `0` and `7` meaning Sunday.
+/// As in classic cron, when both day fields are restricted a day matches if either does;
+/// a field starting with `*` (including steps such as `*/2`) counts as unrestricted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    dom_restricted: bool,
+    dow_restricted: bool,
+    source: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronError(String);
+
+impl fmt::Display for CronError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for CronError {}
+
+/// How far ahead `next_after` looks before concluding an expression never fires (e.g. `0 0 31 2 *`)
+const MAX_LOOKAHEAD_DAYS: i64 = 366 * 5;
+
+impl Schedule {
+    pub fn parse(expression: &str) -> Result<Self, CronError> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, dom, month, dow] = fields[..] else {
+            return Err(CronError(format!(
+                "expected 5 fields (minute hour day-of-month month day-of-week), got {} in '{}'",
+                fields.len(),
+                expression
+            )));
+        };
+
+        let mut days_of_week = parse_field(dow, 0, 7, "day-of-week")?;
+        // Fold 7 (Sunday) onto 0
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week = (days_of_week & !(1 << 7)) | 1;
+        }
+
+        Ok(Self {
+            minutes: parse_field(minute, 0, 59, "minute")?,
+            hours: parse_field(hour, 0, 23, "hour")?,
+            days_of_month: parse_field(dom, 1, 31, "day-of-month")?,
+            months: parse_field(month, 1, 12, "month")?,
+            days_of_week,
+            dom_restricted: !dom.starts_with('*'),
+            dow_restricted: !dow.starts_with('*'),
+            source: expression.to_string(),
+        })
+    }
+
+    fn matches_date(&self, date: NaiveDate) -> bool {
+        if self.months & (1 << date.month()) == 0 {
+            return false;
+        }
+        let dom = self.days_of_month & (1 << date.day()) != 0;
+        let dow = self.days_of_week & (1 << date.weekday().num_days_from_sunday()) != 0;
+        match (self.dom_restricted, self.dow_restricted) {
+            (true, true) => dom || dow,
+            _ => dom && dow,
+        }
+    }
+
+    /// First moment strictly after `after` (at minute precision) that matches the schedule
+    pub fn next_after<Tz: TimeZone>(&self, after: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let tz = after.timezone();
+        let start = after.naive_local() + Duration::minutes(1);
+        let mut date = start.date();
+        let (mut min_hour, mut min_minute) = (start.hour(), start.minute());
+
+        for _ in 0..MAX_LOOKAHEAD_DAYS {
+            if self.matches_date(date) {
+                for hour in min_hour..24 {
+                    if self.hours & (1 << hour) == 0 {
+                        continue;
+                    }
+                    let first_minute = if hour == min_hour { min_minute } else { 0 };
+                    for minute in first_minute..60 {
+                        if self.minutes & (1 << minute) == 0 {
+                            continue;
+                        }
+                        let naive = date.and_hms_opt(hour, minute, 0)?;
+                        // Skip local times that don't exist because of a DST gap
+                        if let Some(time) = tz.from_local_datetime(&naive).earliest() {
+                            return Some(time);
+                        }
+                    }
+                }
+            }
+            date = date.succ_opt()?;
+            min_hour = 0;
+            min_minute = 0;
+        }
+        None
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = CronError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// Parses one cron field into a bitmask with bit `n` set for every allowed value `n`
+fn parse_field(field: &str, min: u32, max: u32, name: &str) -> Result<u64, CronError> {
+    let invalid = |reason: &str| CronError(format!("invalid {} field '{}': {}", name, field, reason));
+    let mut mask = 0u64;
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step.parse().map_err(|_| invalid("step is not a number"))?;
+                if step == 0 {
+                    return Err(invalid("step must be positive"));
+                }
+                (range, step)
+            }
+            None => (part, 1),
+        };
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            let start = start.parse().map_err(|_| invalid("range start is not a number"))?;
+            let end = end.parse().map_err(|_| invalid("range end is not a number"))?;
+            (start, end)
+        } else {
+            let value = range.parse().map_err(|_| invalid("value is not a number"))?;
+            // `5/10` means "from 5 to the end in steps of 10"
+            if part.contains('/') {
+                (value, max)
+            } else {
+                (value, value)
+            }
+        };
+
+        if start < min || end > max || start > end {
+            return Err(invalid(&format!("values must be within {}-{}", min, max)));
+        }
+
+        for value in (start..=end).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+    Ok(mask)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{FixedOffset, Utc};
+
+    use super::*;
+
+    fn at(date: &str, time: &str) -> DateTime<Utc> {
+        let naive = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .unwrap()
+            .and_time(chrono::NaiveTime::parse_from_str(time, "%H:%M").unwrap());
+        Utc.from_utc_datetime(&naive)
+    }
+
+    fn next(expression: &str, date: &str, time: &str) -> DateTime<Utc> {
+        Schedule::parse(expression).unwrap().next_after(&at(date, time)).unwrap()
+    }
+
+    fn matching_days(expression: &str, from: &str, days: u64) -> Vec<u32> {
+        let schedule = Schedule::parse(expression).unwrap();
+        let from = NaiveDate::parse_from_str(from, "%Y-%m-%d").unwrap();
+        from.iter_days()
+            .take(days as usize)
+            .filter(|date| schedule.matches_date(*date))
+            .map(|date| date.day())
+            .collect()
+    }
+
+    #[test]
+    fn parses_values_ranges_lists_and_steps() {
+        assert_eq!(parse_field("5", 0, 59, "minute").unwrap(), 1 << 5);
+        assert_eq!(parse_field("1-3", 0, 59, "minute").unwrap(), 0b1110);
+        assert_eq!(parse_field("1,3,5", 0, 59, "minute").unwrap(), 0b101010);
+        assert_eq!(parse_field("*/20", 0, 59, "minute").unwrap(), 1 | 1 << 20 | 1 << 40);
+        assert_eq!(parse_field("8-14/3", 0, 23, "hour").unwrap(), 1 << 8 | 1 << 11 | 1 << 14);
+        assert_eq!(parse_field("50/5", 0, 59, "minute").unwrap(), 1 << 50 | 1 << 55);
+    }
+
+    #[test]
+    fn rejects_invalid_fields() {
+        assert!(Schedule::parse("0 9 * *").is_err());
+        assert!(Schedule::parse("60 9 * * *").is_err());
+        assert!(Schedule::parse("0 9 5-1 * *").is_err());
+        assert!(Schedule::parse("*/0 9 * * *").is_err());
+        assert!(Schedule::parse("0 9 * 13 *").is_err());
+        assert!(Schedule::parse("0 nine * * *").is_err());
+    }
+
+    #[test]
+    fn sunday_is_both_zero_and_seven() {
+        assert_eq!(Schedule::parse("0 9 * * 7").unwrap().days_of_week, 1);
+        assert_eq!(Schedule::parse("0 9 * * 0").unwrap().days_of_week, 1);
+    }
+
+    #[test]
+    fn restricted_day_fields_are_or_ed() {
+        // March 2025 starts on a Saturday; Mondays are the 3rd, 10th, 17th, 24th and 31st
+        assert_eq!(matching_days("0 9 1,15 * 1", "2025-03-01", 31), vec![1, 3, 10, 15, 17, 24, 31]);
+    }
+
+    #[test]
+    fn stepped_star_day_field_is_unrestricted() {
+        // Odd weekdays only, not every weekday plus every odd day
+        assert_eq!(
+            matching_days("0 9 */2 * 1-5", "2025-03-01", 14),
+            vec![3, 5, 7, 11, 13]
+        );
+        assert_eq!(matching_days("0 9 1-3 * */7", "2025-03-01", 7), vec![2]);
+    }
+
+    #[test]
+    fn next_after_is_strictly_later() {
+        assert_eq!(next("0 9 * * *", "2025-03-03", "09:00"), at("2025-03-04", "09:00"));
+        assert_eq!(next("0 9 * * *", "2025-03-03", "08:59"), at("2025-03-03", "09:00"));
+        assert_eq!(next("*/15 * * * *", "2025-03-03", "10:07"), at("2025-03-03", "10:15"));
+    }
+
+    #[test]
+    fn next_after_skips_to_weekdays() {
+        // Friday evening to Monday morning
+        assert_eq!(next("30 11 * * 1-5", "2025-03-07", "12:00"), at("2025-03-10", "11:30"));
+    }
+
+    #[test]
+    fn next_after_rolls_over_months_and_years() {
+        assert_eq!(next("0 10 1 * *", "2025-01-31", "10:00"), at("2025-02-01", "10:00"));
+        assert_eq!(next("0 0 31 * *", "2025-04-01", "00:00"), at("2025-05-31", "00:00"));
+        assert_eq!(next("0 10 1 1 *", "2025-06-15", "00:00"), at("2026-01-01", "10:00"));
+        assert_eq!(next("0 0 29 2 *", "2025-03-01", "00:00"), at("2028-02-29", "00:00"));
+    }
+
+    #[test]
+    fn next_after_keeps_the_time_zone() {
+        let offset = FixedOffset::east_opt(3 * 3600).unwrap();
+        let after = at("2025-03-03", "05:00").with_timezone(&offset);
+        let next = Schedule::parse("0 9 * * *").unwrap().next_after(&after).unwrap();
+        assert_eq!(next, at("2025-03-03", "06:00"));
+    }
+
+    #[test]
+    fn impossible_schedule_never_fires() {
+        assert_eq!(Schedule::parse("0 0 31 2 *").unwrap().next_after(&at("2025-01-01", "00:00")), None);
+    }
+}
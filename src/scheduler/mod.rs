@@ -0,0 +1,333 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, Utc};
+use teloxide::{
+    prelude::*,
+    types::ParseMode,
+    utils::html,
+    RequestError,
+};
+
+use crate::config::{BotConfig, SharedBotConfig, VoteMode};
+use crate::database_actions::{day::Day, DatabaseService};
+use crate::digest::{build_digest, send_digest, DigestPeriod};
+use crate::handlers::common::{start_keyboard, today_stats_message, vote_keyboard};
+use crate::handlers::poll_handler::post_today_poll;
+use crate::localization::{resolve_lang, tr, tr_args, Lang};
+
+use config::SchedulerConfig;
+use cron::Schedule;
+
+pub mod config;
+pub mod cron;
+
+/// How far back the reminder looks for the previous voting day; voters of an older day,
+/// e.g. before a holiday, are not reminded
+const REMINDER_LOOKBACK_DAYS: i64 = 7;
+
+/// Jobs the scheduler knows how to run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Job {
+    MorningPost,
+    Reminder,
+    DailySummary,
+    WeeklyDigest,
+    MonthlyDigest,
+}
+
+impl Job {
+    /// Name the job's runs are persisted under
+    pub fn name(self) -> &'static str {
+        match self {
+            Job::MorningPost => "morning_post",
+            Job::Reminder => "reminder",
+            Job::DailySummary => "daily_summary",
+            Job::WeeklyDigest => "weekly_digest",
+            Job::MonthlyDigest => "monthly_digest",
+        }
+    }
+}
+
+/// Everything a job needs to post to the notification chat
+struct JobContext {
+    bot: Bot,
+    database_service: DatabaseService,
+    /// Read at every run, so settings reloaded since startup are used
+    bot_config: SharedBotConfig,
+}
+
+/// Starts one background task per configured job
+pub fn start_scheduler(
+    bot: Bot,
+    database_service: DatabaseService,
+    bot_config: SharedBotConfig,
+    config: SchedulerConfig,
+) {
+    let jobs = [
+        (Job::MorningPost, &config.morning_post),
+        (Job::Reminder, &config.reminder),
+        (Job::DailySummary, &config.daily_summary),
+        (Job::WeeklyDigest, &config.weekly_digest),
+        (Job::MonthlyDigest, &config.monthly_digest),
+    ];
+
+    if jobs.iter().all(|(_, expression)| expression.is_none()) {
+        return;
+    }
+    // The chat and the time zone are only read here, changing them needs a restart
+    let startup_config = bot_config.load();
+    if startup_config.notification_chat_id == 0 {
+        log::warn!("Scheduled jobs are configured but notification_chat_id is not set, scheduler disabled");
+        return;
+    }
+
+    let Some(offset) = startup_config.utc_offset() else {
+        log::error!("Invalid utc_offset_minutes: {}, scheduler disabled", startup_config.utc_offset_minutes);
+        return;
+    };
+    let catch_up = Duration::minutes(config.catch_up_minutes as i64);
+    let context = Arc::new(JobContext {
+        bot,
+        database_service,
+        bot_config,
+    });
+
+    for (job, expression) in jobs {
+        let Some(expression) = expression else {
+            continue;
+        };
+        match Schedule::parse(expression) {
+            Ok(schedule) => {
+                log::info!("Scheduling {} at '{}'", job.name(), schedule);
+                tokio::spawn(job_loop(job, schedule, offset, catch_up, context.clone()));
+            }
+            Err(e) => log::error!("Invalid schedule for {}: {}", job.name(), e),
+        }
+    }
+}
+
+async fn job_loop(
+    job: Job,
+    schedule: Schedule,
+    offset: FixedOffset,
+    catch_up: Duration,
+    context: Arc<JobContext>,
+) {
+    // Starting in the past lets a run missed during a short restart still happen;
+    // the persisted claim keeps it from happening twice
+    let mut cursor = Utc::now().with_timezone(&offset) - catch_up;
+
+    loop {
+        let Some(slot) = schedule.next_after(&cursor) else {
+            log::warn!("Schedule '{}' of {} never fires again", schedule, job.name());
+            return;
+        };
+        cursor = slot;
+
+        let now = Utc::now();
+        if let Ok(delay) = (slot.with_timezone(&Utc) - now).to_std() {
+            tokio::time::sleep(delay).await;
+        }
+
+        // A slot that comes while the database is down waits for it, but no longer
+        // than the catch-up window a restart would allow
+        if !wait_for_database(&context.database_service, slot.with_timezone(&Utc) + catch_up).await {
+            log::warn!("Database unavailable, skipping scheduled {} for {}", job.name(), slot);
+            continue;
+        }
+
+        let slot_key = mongodb::bson::DateTime::from_millis(slot.timestamp_millis());
+        match context.database_service.claim_job_run(job.name(), slot_key).await {
+            Ok(true) => {
+                log::info!("Running scheduled {} for {}", job.name(), slot);
+                if let Err(e) = run_job(job, &context, slot.date_naive()).await {
+                    log::error!("Scheduled {} failed: {}", job.name(), e);
+                }
+            }
+            Ok(false) => log::info!("Scheduled {} for {} already done, skipping", job.name(), slot),
+            // Not knowing whether the slot ran, skipping is better than posting twice
+            Err(e) => log::error!("Failed to claim scheduled {} for {}: {}", job.name(), slot, e),
+        }
+    }
+}
+
+/// Waits until the database is available or `deadline` passes, returning whether it is available
+async fn wait_for_database(database_service: &DatabaseService, deadline: DateTime<Utc>) -> bool {
+    while !database_service.is_available() {
+        if Utc::now() >= deadline {
+            return false;
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+    }
+    true
+}
+
+/// Runs `job` for the schedule slot on the local date `date`
+async fn run_job(job: Job, context: &JobContext, date: NaiveDate) -> Result<(), RequestError> {
+    let bot_config = context.bot_config.load_full();
+    let chat_id = ChatId(bot_config.notification_chat_id);
+    let lang = resolve_lang(&context.database_service, Some(chat_id.0), None, bot_config.default_lang()).await;
+
+    match job {
+        Job::MorningPost => morning_post(context, &bot_config, chat_id, lang).await,
+        Job::Reminder => reminder(context, &bot_config, chat_id, lang).await,
+        Job::DailySummary => daily_summary(context, &bot_config, chat_id, lang).await,
+        Job::WeeklyDigest => digest(context, &bot_config, chat_id, lang, DigestPeriod::Week, date).await,
+        Job::MonthlyDigest => digest(context, &bot_config, chat_id, lang, DigestPeriod::Month, date).await,
+    }
+}
+
+async fn morning_post(
+    context: &JobContext,
+    bot_config: &BotConfig,
+    chat_id: ChatId,
+    lang: Lang,
+) -> Result<(), RequestError> {
+    match bot_config.vote_mode {
+        VoteMode::Poll => {
+            post_today_poll(&context.bot, &context.database_service, bot_config, chat_id, lang).await
+        }
+        VoteMode::Buttons => {
+            context
+                .bot
+                .send_message(
+                    chat_id,
+                    tr_args(lang, "vote_card", &[("target", &bot_config.target_name)]),
+                )
+                .reply_markup(start_keyboard(lang))
+                .await?;
+            Ok(())
+        }
+    }
+}
+
+async fn reminder(
+    context: &JobContext,
+    bot_config: &BotConfig,
+    chat_id: ChatId,
+    lang: Lang,
+) -> Result<(), RequestError> {
+    let database_service = &context.database_service;
+    let today_start = Day::today_start();
+    let lookback_start =
+        mongodb::bson::DateTime::from_millis(today_start.timestamp_millis() - REMINDER_LOOKBACK_DAYS * 86400000);
+
+    // The previous voting day rather than the previous calendar day, so a Monday run
+    // reminds Friday's voters
+    let (yesterday, today) = match (
+        database_service.get_latest_voted_day_in_range(lookback_start, today_start).await,
+        database_service.check_today_document().await,
+    ) {
+        (Ok(Some(yesterday)), Ok(today)) => (yesterday, today),
+        (Ok(None), _) => {
+            log::info!("Nothing to remind about: nobody voted in the last {} days", REMINDER_LOOKBACK_DAYS);
+            return Ok(());
+        }
+        (Err(e), _) | (_, Err(e)) => {
+            log::error!("Failed to load the days to remind about: {}", e);
+            return Ok(());
+        }
+    };
+
+    let voted_today: Vec<i64> = today.votes_yes.iter().chain(&today.votes_no).copied().collect();
+    let mut to_remind: Vec<i64> = yesterday
+        .votes_yes
+        .iter()
+        .chain(&yesterday.votes_no)
+        .copied()
+        .filter(|user_id| !voted_today.contains(user_id))
+        .collect();
+    to_remind.sort_unstable();
+    to_remind.dedup();
+
+    if to_remind.is_empty() {
+        return Ok(());
+    }
+
+    let profiles = database_service
+        .get_user_profiles(&to_remind)
+        .await
+        .unwrap_or_else(|e| {
+            log::warn!("Failed to load user profiles for the reminder: {}", e);
+            Vec::new()
+        });
+    let mentions: Vec<String> = to_remind
+        .iter()
+        .map(|&user_id| {
+            let name = profiles
+                .iter()
+                .find(|profile| profile.user_id == user_id)
+                .map(|profile| profile.display_name())
+                .unwrap_or_else(|| user_id.to_string());
+            html::user_mention(UserId(user_id as u64), &name)
+        })
+        .collect();
+
+    let text = tr_args(
+        lang,
+        "reminder",
+        &[
+            ("mentions", &mentions.join(", ")),
+            ("target", &html::escape(&bot_config.target_name)),
+        ],
+    );
+    let request = context.bot.send_message(chat_id, text).parse_mode(ParseMode::Html);
+    match bot_config.vote_mode {
+        VoteMode::Buttons => request.reply_markup(vote_keyboard(lang)).await?,
+        VoteMode::Poll => request.await?,
+    };
+    Ok(())
+}
+
+async fn daily_summary(
+    context: &JobContext,
+    bot_config: &BotConfig,
+    chat_id: ChatId,
+    lang: Lang,
+) -> Result<(), RequestError> {
+    let today = match context.database_service.check_today_document().await {
+        Ok(today) => today,
+        Err(e) => {
+            log::error!("Failed to load today's document for the summary: {}", e);
+            return Ok(());
+        }
+    };
+
+    let verdict_key = if today.votes_yes.is_empty() && today.votes_no.is_empty() {
+        "verdict_no_votes"
+    } else if today.votes_yes.len() > today.votes_no.len() {
+        "verdict_late"
+    } else if today.votes_no.len() > today.votes_yes.len() {
+        "verdict_unlate"
+    } else {
+        "verdict_tie"
+    };
+    let text = format!(
+        "{}\n\n{}\n\n{}",
+        tr(lang, "daily_summary_title"),
+        today_stats_message(lang, &today, None),
+        tr_args(lang, verdict_key, &[("target", &bot_config.target_name)]),
+    );
+
+    context.bot.send_message(chat_id, text).await?;
+    Ok(())
+}
+
+async fn digest(
+    context: &JobContext,
+    bot_config: &BotConfig,
+    chat_id: ChatId,
+    lang: Lang,
+    period: DigestPeriod,
+    date: NaiveDate,
+) -> Result<(), RequestError> {
+    let digest = match build_digest(&context.database_service, period, date).await {
+        Ok(digest) => digest,
+        Err(e) => {
+            log::error!("Failed to build the {:?} digest: {}", period, e);
+            return Ok(());
+        }
+    };
+
+    send_digest(&context.bot, chat_id, lang, &digest, &bot_config.target_name).await
+}
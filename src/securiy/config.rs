@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
 pub struct BotSecurityConfig {
     /// Maximum number of requests allowed within the time window
     pub request_limit: u32,
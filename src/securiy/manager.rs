@@ -1,6 +1,8 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+use arc_swap::ArcSwap;
 use tokio::sync::Mutex;
 use tokio::time::sleep;
 
@@ -15,10 +17,22 @@ struct UserRequestInfo {
 }
 
 pub struct SecurityManager {
-    /// Security configuration
-    config: BotSecurityConfig,
+    /// Security configuration, replaced as a whole when the config file is reloaded
+    config: ArcSwap<BotSecurityConfig>,
     /// Map of user IDs to their request information, protected by a mutex for thread safety
     request_map: Mutex<HashMap<i64, UserRequestInfo>>,
+    /// Users banned from the console; unlike the blacklist this survives config reloads but
+    /// not a restart
+    banned: Mutex<HashSet<i64>>,
+}
+
+/// Rate limiting state of one user, as shown by the console
+pub struct UserRate {
+    pub user_id: i64,
+    /// Requests within the current time window
+    pub requests: usize,
+    /// How long the user stays blocked, if they hit the limit
+    pub blocked_for: Option<Duration>,
 }
 
 pub enum CheckResult {
@@ -31,11 +45,79 @@ pub enum CheckResult {
 impl SecurityManager {
     pub async fn new(config: BotSecurityConfig) -> Self {
         Self { 
-            config,
+            config: ArcSwap::from_pointee(config),
             request_map: Mutex::new(HashMap::new()),
+            banned: Mutex::new(HashSet::new()),
         }
     }
 
+    /// The limits and lists in effect
+    pub fn config(&self) -> Arc<BotSecurityConfig> {
+        self.config.load_full()
+    }
+
+    /// Blocks every request of `user_id`; returns `false` if they were already banned
+    pub async fn ban(&self, user_id: i64) -> bool {
+        self.banned.lock().await.insert(user_id)
+    }
+
+    /// Lifts a ban from [`Self::ban`]; returns `false` if the user wasn't banned
+    pub async fn unban(&self, user_id: i64) -> bool {
+        self.banned.lock().await.remove(&user_id)
+    }
+
+    /// Users banned with [`Self::ban`], sorted
+    pub async fn banned(&self) -> Vec<i64> {
+        let mut banned: Vec<i64> = self.banned.lock().await.iter().copied().collect();
+        banned.sort_unstable();
+        banned
+    }
+
+    /// Users with requests in the current time window, busiest first
+    pub async fn user_rates(&self) -> Vec<UserRate> {
+        let config = self.config.load_full();
+        let time_window = Duration::from_secs(config.time_window_seconds as u64);
+        let now = Instant::now();
+        let request_map = self.request_map.lock().await;
+
+        let mut rates: Vec<UserRate> = request_map
+            .iter()
+            .filter_map(|(&user_id, info)| {
+                let recent: Vec<&Instant> = info
+                    .request_timestamps
+                    .iter()
+                    .filter(|&&timestamp| now.duration_since(timestamp) < time_window)
+                    .collect();
+                let oldest = **recent.first()?;
+                let blocked_for = (recent.len() >= config.request_limit as usize)
+                    .then(|| time_window.saturating_sub(now.duration_since(oldest)));
+                Some(UserRate {
+                    user_id,
+                    requests: recent.len(),
+                    blocked_for,
+                })
+            })
+            .collect();
+        rates.sort_by(|a, b| b.requests.cmp(&a.requests).then(a.user_id.cmp(&b.user_id)));
+        rates
+    }
+
+    /// Switches to new limits and lists; the request history is kept, so a reload
+    /// doesn't give rate-limited users a fresh window
+    pub fn update_config(&self, config: BotSecurityConfig) {
+        self.config.store(Arc::new(config));
+    }
+
+    /// Whether every request of `user_id` is refused, by a console ban or the blacklist,
+    /// regardless of their request rate
+    pub async fn is_blocked(&self, user_id: i64) -> bool {
+        if self.banned.lock().await.contains(&user_id) {
+            return true;
+        }
+        let config = self.config.load_full();
+        config.ddos_protection_enabled && config.blacklist.contains(&user_id)
+    }
+
     /// Checks if a request from a user should be allowed or blocked based on rate limits
     /// 
     /// # Arguments
@@ -45,16 +127,21 @@ impl SecurityManager {
     /// * `CheckResult::Pass` if the request is allowed
     /// * `CheckResult::Block(Duration)` if the request is blocked, with the duration to wait
     pub async fn check_request_rate(&self, user_id: i64) -> CheckResult {
+        // Bans from the console apply even with DDoS protection disabled
+        if self.banned.lock().await.contains(&user_id) {
+            return CheckResult::Block(Duration::MAX);
+        }
+        let config = self.config.load_full();
         // If DDoS protection is disabled, always allow the request
-        if !self.config.ddos_protection_enabled {
+        if !config.ddos_protection_enabled {
             return CheckResult::Pass;
         }
         // If the user is in the blacklist, always block the request
-        if self.config.blacklist.contains(&user_id) {
+        if config.blacklist.contains(&user_id) {
             return CheckResult::Block(Duration::MAX);
         }
         // If the user is in the whitelist, always allow the request
-        if self.config.whitelist.contains(&user_id) {
+        if config.whitelist.contains(&user_id) {
             return CheckResult::Pass;
         }
 
@@ -68,7 +155,7 @@ impl SecurityManager {
         });
         
         // Clean up old timestamps (older than the configured time window)
-        let time_window = Duration::from_secs(self.config.time_window_seconds as u64);
+        let time_window = Duration::from_secs(config.time_window_seconds as u64);
         let window_start = now - time_window;
         
         // If it's been more than the time window since the last reset, reset the timestamps
@@ -81,7 +168,7 @@ impl SecurityManager {
         }
         
         // Check if the user has exceeded the rate limit
-        if user_info.request_timestamps.len() >= self.config.request_limit as usize {
+        if user_info.request_timestamps.len() >= config.request_limit as usize {
             // If rate limit exceeded, calculate how long to wait
             if let Some(oldest_timestamp) = user_info.request_timestamps.first() {
                 let time_to_wait = time_window - (now - *oldest_timestamp);